@@ -0,0 +1,29 @@
+use bayes_star::common::discover::{discover_rules, store_discovered_rules};
+use bayes_star::common::resources::FactoryResources;
+use bayes_star::common::setup::parse_configuration_options;
+use bayes_star::common::train::do_training;
+
+extern crate log;
+
+const RULES_TO_PRINT: usize = 20;
+
+fn main() {
+    let config = parse_configuration_options();
+    let resources = FactoryResources::new(&config).expect("Couldn't create resources.");
+    let rules = discover_rules(&resources).expect("Rule discovery failed.");
+    println!("{:<12} {:<8} RULE", "GAIN", "HELD OUT");
+    for rule in rules.iter().take(RULES_TO_PRINT) {
+        println!(
+            "{:<12.6} {:<8} {}",
+            rule.improvement,
+            rule.held_out_examples,
+            rule.implication.unique_key()
+        );
+    }
+    if let Some(count) = config.store_discovered_rules {
+        let stored = store_discovered_rules(&resources, &rules, count).expect("Storing rules failed.");
+        println!("stored {} rules, retraining", stored);
+        do_training(&resources).expect("Error in training.");
+    }
+    println!("main finishes");
+}
@@ -0,0 +1,34 @@
+use bayes_star::common::model::InferenceModel;
+use bayes_star::common::proposition_db::RedisBeliefTable;
+use bayes_star::common::resources::FactoryResources;
+use bayes_star::common::setup::parse_configuration_options;
+use bayes_star::inference::forward::{facts_from_belief_table, ForwardChainer};
+
+extern crate log;
+
+fn main() {
+    let config = parse_configuration_options();
+    let resources = FactoryResources::new(&config).expect("Couldn't create resources.");
+    let model = InferenceModel::new_shared(&resources).expect("Couldn't load the model.");
+    let facts = facts_from_belief_table(&resources).expect("Reading facts failed.");
+    let derived_store = if config.store_derived {
+        RedisBeliefTable::clear_derived(&resources.redis).expect("Couldn't clear the derived-belief store.");
+        Some(RedisBeliefTable::new_derived_shared(&resources.redis).expect("Couldn't open the derived-belief store."))
+    } else {
+        None
+    };
+    let report = ForwardChainer::new(&config, model)
+        .run(&facts, derived_store.as_deref())
+        .expect("Forward chaining failed.");
+    println!("{:<12} {:<6} PROPOSITION", "PROBABILITY", "ROUND");
+    for belief in &report.derived {
+        println!("{:<12.8} {:<6} {:?}", belief.probability, belief.round, belief.proposition);
+    }
+    println!(
+        "{} facts, {} derived in {} rounds, fixpoint {}",
+        facts.len(),
+        report.derived.len(),
+        report.rounds,
+        report.reached_fixpoint
+    );
+}
@@ -5,8 +5,8 @@ use bayes_star::common::setup::{parse_configuration_options, ConfigurationOption
 use bayes_star::common::resources::FactoryResources;
 use bayes_star::common::test::ReplState;
 use bayes_star::common::train::TrainingPlan;
-use bayes_star::inference::graph::PropositionGraph;
-use bayes_star::inference::inference::Inferencer;
+use bayes_star::inference::graph::{GraphBuildOptions, PropositionGraph};
+use bayes_star::inference::inferencer::Inferencer;
 use bayes_star::inference::table::PropositionNode;
 
 extern crate log;
@@ -24,6 +24,9 @@ fn setup_test_scenario(scenario_name:&str, test_scenario:&str, repl_state:&mut R
         ("long_chain", "prior") => vec![],
         ("long_chain", "set_0_1") => vec![("alpha0[sub=test_Jack0]", 1f64)],
         ("long_chain", "set_n_1") => vec![("alpha10[sub=test_Jack0]", 1f64)],
+        ("tied_chain", "prior") => vec![],
+        ("tied_chain", "set_0_1") => vec![("alpha0[sub=test_Jack0]", 1f64)],
+        ("tied_chain", "set_n_1") => vec![("alpha10[sub=test_Jack0]", 1f64)],
         ("mid_chain", "set_0_1") => vec![("alpha0[sub=test_Jack0]", 1f64)],
         ("mid_chain", "set_n_1") => vec![("alpha4[sub=test_Jack0]", 1f64)],
         _ => panic!("Case name not recognized"),
@@ -37,34 +40,51 @@ pub fn run_inference_rounds(
     resources: &FactoryResources,
 ) -> Result<(), Box<dyn Error>> {
     let plan = TrainingPlan::new(&resources.redis)?;
-    let model = InferenceModel::new_shared(&resources).unwrap();
+    let model = InferenceModel::new_shared(resources).unwrap();
     let test_questions = plan.get_test_questions().unwrap();
     let target = &test_questions[config.test_example.unwrap() as usize];
     let fact_memory = EmptyBeliefTable::new_shared(&resources.redis)?;
-    let proposition_graph = PropositionGraph::new_shared(model.graph.clone(), target)?;
+    let proposition_graph = PropositionGraph::new_shared_for_targets(
+        model.graph.clone(),
+        std::slice::from_ref(target),
+        &GraphBuildOptions::from_config(config),
+    )?;
     proposition_graph.visualize();
+    let truncation = &proposition_graph.truncation;
+    if truncation.is_truncated() {
+        println!(
+            "graph truncated: {} frontier nodes, depth limit {} node limit {} time limit {}",
+            truncation.frontier.len(),
+            truncation.hit_depth_limit,
+            truncation.hit_node_limit,
+            truncation.hit_time_limit
+        );
+    }
     let mut inferencer =
-        Inferencer::new_mutable(&config, model.clone(), proposition_graph.clone(), fact_memory)?;
+        Inferencer::new_mutable(config, model.clone(), proposition_graph.clone(), fact_memory)?;
     inferencer.initialize_chart()?;
     let mut repl = ReplState::new(inferencer);
-    if config.test_scenario.clone().unwrap() == "show".to_string() {
+    if config.test_scenario.as_deref() == Some("show") {
         for (i, x) in repl.inferencer.bfs_order.iter().enumerate() {
             println!("{} {:?}", i , x);
         }
     } else {
         repl.inferencer.clear_marginal_output_file()?;
         repl.inferencer.log_table_to_file()?;
-        let focus = setup_test_scenario(&config.scenario_name,&config.test_scenario.as_ref().unwrap(), &mut repl)?;
-        if focus.is_some() {
-            for _i in 0..50 {
-                repl.inferencer.do_fan_out_from_node(&focus.clone().unwrap())?;
-                repl.inferencer.log_table_to_file()?;
-            }
-        } else {
-            for _i in 0..50 {
-                repl.inferencer.do_full_forward_and_backward()?;
-                repl.inferencer.log_table_to_file()?;
+        let focus = setup_test_scenario(&config.scenario_name,config.test_scenario.as_ref().unwrap(), &mut repl)?;
+        let report = repl
+            .inferencer
+            .propagate_until_converged(focus.as_ref(), &mut |inferencer| inferencer.log_table_to_file())?;
+        println!(
+            "{:?} iterations {} message updates {} residual {} converged {}",
+            report.schedule, report.iterations, report.message_updates, report.residual, report.converged
+        );
+        match repl.inferencer.compute_exact_marginals() {
+            Ok(exact) => {
+                let approximate = repl.inferencer.build_marginal_table()?;
+                println!("max difference from exact {}", exact.max_difference(&approximate));
             }
+            Err(e) => println!("exact inference skipped: {}", e),
         }
     }
     Ok(())
@@ -0,0 +1,34 @@
+use bayes_star::common::resources::FactoryResources;
+use bayes_star::common::setup::parse_configuration_options;
+use bayes_star::model::inspect::summarize_all_implications;
+use colored::*;
+
+extern crate log;
+
+fn main() {
+    let config = parse_configuration_options();
+    let resources = FactoryResources::new(&config).expect("Couldn't create resources.");
+    let summaries = summarize_all_implications(&resources).expect("Reading weights failed.");
+    if config.print_json {
+        let json = serde_json::to_string_pretty(&summaries).expect("Serializing weights failed.");
+        println!("{}", json);
+        return;
+    }
+    println!(
+        "{:<10} {:<10} {:<10} {:<24} {:<24} IMPLICATION",
+        "INFLUENCE", "P(+|T)", "P(+|F)", "+>- / +>+", "->- / ->+"
+    );
+    for summary in &summaries {
+        let positive = format!("{:.4} / {:.4}", summary.positive_weights[0], summary.positive_weights[1]);
+        let negative = format!("{:.4} / {:.4}", summary.negative_weights[0], summary.negative_weights[1]);
+        println!(
+            "{:<10} {:<10} {:<10} {:<24} {:<24} {}",
+            format!("{:.4}", summary.influence).yellow(),
+            format!("{:.4}", summary.probability_if_premise_true).green(),
+            format!("{:.4}", summary.probability_if_premise_false).red(),
+            positive,
+            negative,
+            summary.implication
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use crate::{
+    common::{
+        graph::InferenceGraph,
+        interface::BeliefTable,
+        proposition_db::RedisBeliefTable,
+        random::with_global_rng,
+        resources::FactoryResources,
+        train::TrainingPlan,
+    },
+    model::{
+        choose::ground_premise_for_conclusion,
+        creators::{implication, predicate, role, variable},
+        exponential::{compute_expected_features, compute_potential, do_sgd_update},
+        objects::{Argument, Domain, Predicate, PredicateFactor, PredicateGroup, Proposition, RoleMap},
+        weights::{negative_feature, positive_feature, CLASS_LABELS},
+    },
+};
+use rand::seq::SliceRandom;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+const HELD_OUT_FRACTION: f64 = 0.2;
+const TRAINING_EPOCHS: usize = 20;
+// Only this many of the best single-premise rules per conclusion are combined into conjunctions.
+const CONJUNCTION_BEAM: usize = 5;
+const PROBABILITY_FLOOR: f64 = 1e-6;
+
+#[derive(Debug, Clone)]
+pub struct RuleCandidate {
+    pub implication: PredicateFactor,
+    // Mean held-out log-likelihood gain per example over predicting the conclusion's base rate.
+    pub improvement: f64,
+    pub held_out_examples: usize,
+}
+
+// A predicate function with the names and domains of its roles, e.g. `like[sub:Jack,obj:Jill]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PredicateShape {
+    function: String,
+    roles: Vec<(String, Domain)>,
+}
+
+impl PredicateShape {
+    fn from_proposition(proposition: &Proposition) -> Option<PredicateShape> {
+        let mut roles = vec![];
+        for labeled in proposition.predicate.roles() {
+            match &labeled.argument {
+                Argument::Constant(constant) => roles.push((labeled.role_name.clone(), constant.domain)),
+                Argument::Variable(_) => return None,
+            }
+        }
+        Some(PredicateShape {
+            function: proposition.predicate.function.clone(),
+            roles,
+        })
+    }
+
+    fn quantified(&self) -> Predicate {
+        let roles = self
+            .roles
+            .iter()
+            .map(|(role_name, domain)| role(role_name.clone(), variable(*domain)))
+            .collect();
+        predicate(self.function.clone(), roles)
+    }
+}
+
+// Every way to fill each premise role from a distinct conclusion role of the same domain.
+// A `RoleMap` maps conclusion role names to premise role names.
+fn enumerate_role_maps(conclusion: &PredicateShape, premise: &PredicateShape) -> Vec<RoleMap> {
+    fn run(
+        conclusion: &PredicateShape,
+        premise: &PredicateShape,
+        index: usize,
+        used: &mut HashSet<String>,
+        current: &mut HashMap<String, String>,
+        result: &mut Vec<RoleMap>,
+    ) {
+        if index == premise.roles.len() {
+            result.push(RoleMap::new(current.clone()));
+            return;
+        }
+        let (premise_role, premise_domain) = &premise.roles[index];
+        for (conclusion_role, conclusion_domain) in &conclusion.roles {
+            if conclusion_domain != premise_domain || used.contains(conclusion_role) {
+                continue;
+            }
+            used.insert(conclusion_role.clone());
+            current.insert(conclusion_role.clone(), premise_role.clone());
+            run(conclusion, premise, index + 1, used, current, result);
+            current.remove(conclusion_role);
+            used.remove(conclusion_role);
+        }
+    }
+    let mut result = vec![];
+    run(conclusion, premise, 0, &mut HashSet::new(), &mut HashMap::new(), &mut result);
+    result
+}
+
+fn is_identity(conclusion: &PredicateShape, premise: &PredicateShape, role_map: &RoleMap) -> bool {
+    conclusion.function == premise.function && role_map.role_map.iter().all(|(from, to)| from == to)
+}
+
+fn premise_probability(
+    implication: &PredicateFactor,
+    conclusion: &Proposition,
+    observations: &HashMap<Proposition, f64>,
+) -> Result<Option<f64>, Box<dyn Error>> {
+    let premise = ground_premise_for_conclusion(implication, conclusion)?;
+    let mut product = 1f64;
+    for term in &premise.terms {
+        match observations.get(term) {
+            Some(probability) => product *= probability,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(product))
+}
+
+fn candidate_features(key: &str, premise_probability: f64) -> Vec<HashMap<String, f64>> {
+    CLASS_LABELS
+        .iter()
+        .map(|class_label| {
+            HashMap::from([
+                (positive_feature(key, *class_label), premise_probability),
+                (negative_feature(key, *class_label), 1f64 - premise_probability),
+            ])
+        })
+        .collect()
+}
+
+fn predict_in_memory(weights: &HashMap<String, f64>, features: &[HashMap<String, f64>]) -> f64 {
+    let potential0 = compute_potential(weights, &features[0]);
+    let potential1 = compute_potential(weights, &features[1]);
+    potential1 / (potential0 + potential1)
+}
+
+// Fits the same log-linear form as `ExponentialModel`, in memory, so scoring a candidate
+// does not touch the stored weights.
+fn fit_in_memory(key: &str, examples: &[(f64, f64)]) -> HashMap<String, f64> {
+    let mut weights = HashMap::new();
+    for class_label in CLASS_LABELS {
+        weights.insert(positive_feature(key, class_label), 0f64);
+        weights.insert(negative_feature(key, class_label), 0f64);
+    }
+    for _epoch in 0..TRAINING_EPOCHS {
+        for (premise, gold) in examples {
+            let features = candidate_features(key, *premise);
+            let probability = predict_in_memory(&weights, &features);
+            for class_label in CLASS_LABELS {
+                let (this_gold, this_probability) = if class_label == 0 {
+                    (1f64 - gold, 1f64 - probability)
+                } else {
+                    (*gold, probability)
+                };
+                let class_weights: HashMap<String, f64> = features[class_label]
+                    .keys()
+                    .map(|feature| (feature.clone(), weights[feature]))
+                    .collect();
+                let gold_features = compute_expected_features(this_gold, &features[class_label]);
+                let expected_features =
+                    compute_expected_features(this_probability, &features[class_label]);
+                weights.extend(do_sgd_update(&class_weights, &gold_features, &expected_features, false));
+            }
+        }
+    }
+    weights
+}
+
+fn log_likelihood(probability: f64, gold: f64) -> f64 {
+    let clamped = probability.clamp(PROBABILITY_FLOOR, 1f64 - PROBABILITY_FLOOR);
+    gold * clamped.ln() + (1f64 - gold) * (1f64 - clamped).ln()
+}
+
+fn score_candidate(
+    implication: &PredicateFactor,
+    training: &[(Proposition, f64)],
+    held_out: &[(Proposition, f64)],
+    observations: &HashMap<Proposition, f64>,
+) -> Result<Option<RuleCandidate>, Box<dyn Error>> {
+    let collect = |examples: &[(Proposition, f64)]| -> Result<Vec<(f64, f64)>, Box<dyn Error>> {
+        let mut result = vec![];
+        for (conclusion, gold) in examples {
+            if let Some(premise) = premise_probability(implication, conclusion, observations)? {
+                result.push((premise, *gold));
+            }
+        }
+        Ok(result)
+    };
+    let training_pairs = collect(training)?;
+    let held_out_pairs = collect(held_out)?;
+    if training_pairs.is_empty() || held_out_pairs.is_empty() {
+        return Ok(None);
+    }
+    let base_rate = (training_pairs.iter().map(|(_, gold)| gold).sum::<f64>() + 1f64)
+        / (training_pairs.len() as f64 + 2f64);
+    let key = implication.unique_key();
+    let weights = fit_in_memory(&key, &training_pairs);
+    let mut total_gain = 0f64;
+    for (premise, gold) in &held_out_pairs {
+        let probability = predict_in_memory(&weights, &candidate_features(&key, *premise));
+        total_gain += log_likelihood(probability, *gold) - log_likelihood(base_rate, *gold);
+    }
+    Ok(Some(RuleCandidate {
+        implication: implication.clone(),
+        improvement: total_gain / held_out_pairs.len() as f64,
+        held_out_examples: held_out_pairs.len(),
+    }))
+}
+
+// Proposes single-premise rules between every pair of predicate shapes in the training data,
+// then conjunctions of the best few, and scores each by held-out likelihood. Sorted best first.
+pub fn discover_rules(resources: &FactoryResources) -> Result<Vec<RuleCandidate>, Box<dyn Error>> {
+    let plan = TrainingPlan::new(&resources.redis)?;
+    let proposition_db = RedisBeliefTable::new_shared(&resources.redis)?;
+    let mut observations = HashMap::new();
+    let mut examples_by_shape: HashMap<PredicateShape, Vec<(Proposition, f64)>> = HashMap::new();
+    for proposition in plan.get_training_questions()? {
+        let probability = match proposition_db.get_proposition_probability(&proposition)? {
+            Some(probability) => probability,
+            None => continue,
+        };
+        if let Some(shape) = PredicateShape::from_proposition(&proposition) {
+            observations.insert(proposition.clone(), probability);
+            examples_by_shape
+                .entry(shape)
+                .or_default()
+                .push((proposition, probability));
+        }
+    }
+    let mut shapes: Vec<PredicateShape> = examples_by_shape.keys().cloned().collect();
+    shapes.sort_by_key(|shape| format!("{:?}", shape));
+    let mut result = vec![];
+    for conclusion_shape in &shapes {
+        let mut examples = examples_by_shape[conclusion_shape].clone();
+        with_global_rng(|rng| examples.shuffle(rng));
+        let held_out_size = ((examples.len() as f64) * HELD_OUT_FRACTION).ceil() as usize;
+        let (held_out, training) = examples.split_at(held_out_size.min(examples.len()));
+        let conclusion = conclusion_shape.quantified();
+        let mut singles = vec![];
+        for premise_shape in &shapes {
+            for role_map in enumerate_role_maps(conclusion_shape, premise_shape) {
+                if is_identity(conclusion_shape, premise_shape, &role_map) {
+                    continue;
+                }
+                let candidate = implication(
+                    PredicateGroup::new(vec![premise_shape.quantified()]),
+                    conclusion.clone(),
+                    vec![role_map],
+                );
+                if let Some(scored) = score_candidate(&candidate, training, held_out, &observations)? {
+                    singles.push(scored);
+                }
+            }
+        }
+        singles.sort_by(|a, b| b.improvement.total_cmp(&a.improvement));
+        let beam = &singles[..singles.len().min(CONJUNCTION_BEAM)];
+        for (i, first) in beam.iter().enumerate() {
+            for second in &beam[(i + 1)..] {
+                let mut terms = first.implication.premise.terms.clone();
+                terms.extend(second.implication.premise.terms.clone());
+                let mut role_maps = first.implication.role_maps.role_maps.clone();
+                role_maps.extend(second.implication.role_maps.role_maps.clone());
+                let candidate = implication(PredicateGroup::new(terms), conclusion.clone(), role_maps);
+                if let Some(scored) = score_candidate(&candidate, training, held_out, &observations)? {
+                    result.push(scored);
+                }
+            }
+        }
+        result.extend(singles);
+    }
+    result.sort_by(|a, b| b.improvement.total_cmp(&a.improvement));
+    Ok(result)
+}
+
+// Stores the best `count` rules that improve on the base rate.
+pub fn store_discovered_rules(
+    resources: &FactoryResources,
+    rules: &[RuleCandidate],
+    count: usize,
+) -> Result<usize, Box<dyn Error>> {
+    let mut graph = InferenceGraph::new_mutable(resources)?;
+    let mut stored = 0;
+    for rule in rules.iter().filter(|rule| rule.improvement > 0f64).take(count) {
+        info!("storing discovered rule {}", rule.implication.unique_key());
+        graph.store_predicate_implication(&rule.implication)?;
+        stored += 1;
+    }
+    Ok(stored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::in_memory;
+    use crate::model::creators::{constant, proposition, sub};
+
+    #[test]
+    fn recovers_a_planted_implication() {
+        let (_config, resources) = in_memory(&["--seed", "5"]);
+        let mut plan = TrainingPlan::new(&resources.redis).unwrap();
+        let proposition_db = RedisBeliefTable::new_mutable(&resources.redis).unwrap();
+        // sad copies lonely; tall is independent of both.
+        for index in 0..40 {
+            let person = |function: &str| {
+                proposition(function.to_string(), vec![sub(constant(Domain::Jack, format!("jack{}", index)))])
+            };
+            let lonely = (index % 2 == 0) as u8 as f64;
+            let tall = ((index / 2) % 2 == 0) as u8 as f64;
+            for (function, probability) in [("lonely", lonely), ("sad", lonely), ("tall", tall)] {
+                plan.maybe_add_to_training(true, &person(function)).unwrap();
+                proposition_db.store_proposition_probability(&person(function), probability).unwrap();
+            }
+        }
+        let rules = discover_rules(&resources).unwrap();
+        let gain = |premise: &str, conclusion: &str| {
+            rules
+                .iter()
+                .find(|rule| {
+                    rule.implication.premise.terms.len() == 1
+                        && rule.implication.premise.terms[0].function == premise
+                        && rule.implication.conclusion.function == conclusion
+                })
+                .unwrap()
+                .improvement
+        };
+        assert!(gain("lonely", "sad") > 0.3);
+        assert!(gain("tall", "sad") < gain("lonely", "sad") / 10f64);
+        let best = &rules[0].implication;
+        assert!(best.premise.terms.iter().all(|term| term.function != "tall"), "{}", best.unique_key());
+
+        assert_eq!(store_discovered_rules(&resources, &rules, 1).unwrap(), 1);
+        let stored = InferenceGraph::new_shared(&resources).unwrap().get_all_implications().unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].unique_key(), best.unique_key());
+    }
+}
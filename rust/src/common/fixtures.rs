@@ -0,0 +1,103 @@
+use crate::common::graph::InferenceGraph;
+use crate::common::interface::BeliefTable;
+use crate::common::model::InferenceModel;
+use crate::common::proposition_db::RedisBeliefTable;
+use crate::common::resources::FactoryResources;
+use crate::common::setup::{configuration_options_from_args, ConfigurationOptions};
+use crate::inference::graph::{GraphBuildOptions, PropositionGraph};
+use crate::inference::inferencer::Inferencer;
+use crate::model::creators::{conjunction, constant, implication, predicate, proposition, sub, variable};
+use crate::model::objects::{Domain, Entity, PredicateFactor, Proposition, RoleMap};
+use crate::model::weights::{negative_feature, positive_feature};
+use redis::Commands;
+use std::collections::HashMap;
+
+// `function(jack)`, for the one entity the worlds below talk about.
+pub fn jack(function: &str) -> Proposition {
+    proposition(function.to_string(), vec![sub(constant(Domain::Jack, "jack".to_string()))])
+}
+
+// premises(x) -> conclusion(x), over jacks.
+pub fn rule(premises: &[&str], conclusion: &str) -> PredicateFactor {
+    let quantified = |function: &str| predicate(function.to_string(), vec![sub(variable(Domain::Jack))]);
+    implication(
+        conjunction(premises.iter().map(|premise| quantified(premise)).collect()),
+        quantified(conclusion),
+        vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())])); premises.len()],
+    )
+}
+
+// Options for the `one_var` scenario plus the flags in `args`, and resources over an empty
+// in-memory store.
+pub fn in_memory(args: &[&str]) -> (ConfigurationOptions, FactoryResources) {
+    let mut all_args = vec!["test", "--scenario_name", "one_var"];
+    all_args.extend_from_slice(args);
+    let config = configuration_options_from_args(all_args);
+    let resources = FactoryResources::new_in_memory(&config);
+    (config, resources)
+}
+
+// A model kept in memory whose implications have hand-picked weights, so that tests can set up
+// small graphs without training.
+pub struct WeightedWorld {
+    pub config: ConfigurationOptions,
+    pub resources: FactoryResources,
+    graph: Box<InferenceGraph>,
+}
+
+impl WeightedWorld {
+    // `args` are extra command line flags.
+    pub fn new(args: &[&str]) -> WeightedWorld {
+        let (config, resources) = in_memory(args);
+        let mut graph = InferenceGraph::new_mutable(&resources).unwrap();
+        graph
+            .store_entity(&Entity {
+                domain: Domain::Jack,
+                name: "jack".to_string(),
+            })
+            .unwrap();
+        WeightedWorld { config, resources, graph }
+    }
+
+    // premises(x) -> conclusion(x). A positive `weight` makes the conclusion follow the
+    // premises, a negative one makes it oppose them.
+    pub fn link(&mut self, premises: &[&str], conclusion: &str, weight: f64) {
+        let link = rule(premises, conclusion);
+        self.graph.store_predicate_implication(&link).unwrap();
+        let key = link.parameter_key();
+        let connection = self.resources.redis.get_connection().unwrap();
+        for (feature, value) in [
+            (positive_feature(&key, 1), weight),
+            (negative_feature(&key, 1), -weight),
+            (positive_feature(&key, 0), -weight),
+            (negative_feature(&key, 0), weight),
+        ] {
+            connection.borrow_mut().hset::<_, _, _, ()>("weights", feature, value).unwrap();
+        }
+    }
+
+    pub fn facts(&self) -> Box<dyn BeliefTable> {
+        RedisBeliefTable::new_mutable(&self.resources.redis).unwrap()
+    }
+
+    pub fn observe(&self, function: &str, probability: f64) {
+        self.facts().store_proposition_probability(&jack(function), probability).unwrap();
+    }
+
+    pub fn likelihood_ratio(&self, function: &str, ratio: f64) {
+        self.facts().store_likelihood_ratio(&jack(function), ratio).unwrap();
+    }
+
+    // An inferencer over the graph grown back from `targets`, with its chart initialized.
+    pub fn inferencer(&self, targets: &[&str]) -> Box<Inferencer> {
+        let model = InferenceModel::new_shared(&self.resources).unwrap();
+        let targets: Vec<Proposition> = targets.iter().map(|target| jack(target)).collect();
+        let proposition_graph =
+            PropositionGraph::new_shared_for_targets(model.graph.clone(), &targets, &GraphBuildOptions::default())
+                .unwrap();
+        let fact_memory = RedisBeliefTable::new_shared(&self.resources.redis).unwrap();
+        let mut inferencer = Inferencer::new_mutable(&self.config, model, proposition_graph, fact_memory).unwrap();
+        inferencer.initialize_chart().unwrap();
+        inferencer
+    }
+}
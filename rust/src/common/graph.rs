@@ -16,22 +16,31 @@ use crate::{
         }, choose::{extract_existence_factor_for_predicate, extract_existence_factor_for_proposition},
     }, print_blue,
 };
-use redis::{Commands, Connection};
+use redis::Commands;
+use crate::common::redis::RedisConnection;
 use serde::{Deserialize, Serialize};
 use std::{cell::RefCell, error::Error, rc::Rc};
 pub struct InferenceGraph {
-    redis_connection: RefCell<redis::Connection>,
+    redis_connection: RefCell<RedisConnection>,
+    // False under `learned_priors`, where roots get their prior from `PredicatePriors`.
+    existence_backlinks: bool,
 }
 
 impl InferenceGraph {
     pub fn new_mutable(resources: &FactoryResources) -> Result<Box<Self>, Box<dyn Error>> {
         let redis_connection = resources.redis.get_connection()?;
-        Ok(Box::new(InferenceGraph { redis_connection }))
+        Ok(Box::new(InferenceGraph {
+            redis_connection,
+            existence_backlinks: !resources.config.learned_priors,
+        }))
     }
 
     pub fn new_shared(resources: &FactoryResources) -> Result<Rc<Self>, Box<dyn Error>> {
         let redis_connection = resources.redis.get_connection()?;
-        Ok(Rc::new(InferenceGraph { redis_connection }))
+        Ok(Rc::new(InferenceGraph {
+            redis_connection,
+            existence_backlinks: !resources.config.learned_priors,
+        }))
     }
     
     pub fn store_entity(&mut self, entity: &Entity) -> Result<(), Box<dyn Error>> {
@@ -41,20 +50,40 @@ impl InferenceGraph {
             entity.name
         ); // Logging
         set_add(
-            &mut *self.redis_connection.borrow_mut(),
+            &mut self.redis_connection.borrow_mut(),
             &entity.domain.to_string(),
             &entity.name,
         )?;
+        set_add(
+            &mut self.redis_connection.borrow_mut(),
+            &Self::domain_set_name(),
+            &entity.domain.to_string(),
+        )?;
         Ok(())
     }
 
+    fn domain_set_name() -> String {
+        "domains".to_string()
+    }
+
+    // Every domain that has a stored entity, in a fixed order.
+    pub fn get_all_domains(&self) -> Result<Vec<Domain>, Box<dyn Error>> {
+        let names: Vec<String> = set_members(&mut self.redis_connection.borrow_mut(), &Self::domain_set_name())?;
+        let mut domains = names
+            .iter()
+            .map(|name| name.parse::<Domain>())
+            .collect::<Result<Vec<Domain>, String>>()?;
+        domains.sort_by_key(|domain| domain.to_string());
+        Ok(domains)
+    }
+
     pub fn get_entities_in_domain(&self, domain: &Domain) -> Result<Vec<Entity>, Box<dyn Error>> {
         let domain_string = domain.to_string();
-        let names: Vec<String> = set_members(&mut *self.redis_connection.borrow_mut(), &domain_string)?;
+        let names: Vec<String> = set_members(&mut self.redis_connection.borrow_mut(), &domain_string)?;
         Ok(names
             .into_iter()
             .map(|name| Entity {
-                domain: Domain::from_str(&domain_string).expect("Domain not recognized."), // Use the provided domain
+                domain: domain_string.parse::<Domain>().expect("Domain not recognized."), // Use the provided domain
                 name,
             })
             .collect())
@@ -71,7 +100,7 @@ impl InferenceGraph {
     ) -> Result<(), Box<dyn Error>> {
         let record = serialize_record(implication)?;
         set_add(
-            &mut *self.redis_connection.borrow_mut(),
+            &mut self.redis_connection.borrow_mut(),
             &Self::implication_seq_name(),
             &record,
         )?;
@@ -82,6 +111,10 @@ impl InferenceGraph {
         &mut self,
         proposition: &Proposition,
     ) -> Result<(), Box<dyn Error>> {
+        if !self.existence_backlinks {
+            trace!("leaving {:?} as a root with a learned prior", proposition);
+            return Ok(());
+        }
         let implication = extract_existence_factor_for_proposition(proposition)?;
         self.store_predicate_implication(&implication)?;
         Ok(())
@@ -94,7 +127,7 @@ impl InferenceGraph {
         let conclusion = &inference.conclusion;
         let record = serialize_record(inference)?;
         set_add(
-            &mut *self.redis_connection.borrow_mut(),
+            &mut self.redis_connection.borrow_mut(),
             &Self::predicate_backward_set_name(conclusion),
             &record,
         )?;
@@ -120,7 +153,7 @@ impl InferenceGraph {
     }
     pub fn get_all_implications(&self) -> Result<Vec<PredicateFactor>, Box<dyn Error>> {
         let set_members: Vec<String> = set_members(
-            &mut *self.redis_connection.borrow_mut(),
+            &mut self.redis_connection.borrow_mut(),
             &Self::implication_seq_name(),
         )?;
         set_members
@@ -134,7 +167,7 @@ impl InferenceGraph {
         conclusion: &Predicate,
     ) -> Result<Vec<PredicateFactor>, Box<dyn Error>> {
         let set_members: Vec<String> = set_members(
-            &mut *self.redis_connection.borrow_mut(),
+            &mut self.redis_connection.borrow_mut(),
             &Self::predicate_backward_set_name(conclusion),
         )?;
         set_members
@@ -1,5 +1,7 @@
 use std::error::Error;
 
+use serde::Serialize;
+
 use crate::model::objects::{PredicateGroup, PredicateFactor, Predicate, Proposition};
 
 use super::{graph::InferenceGraph, model::InferenceModel, train::TrainingPlan, redis::RedisManager, resources::FactoryResources};
@@ -11,6 +13,26 @@ pub struct PredictStatistics {
     pub probability: f64,
 }
 
+#[derive(Serialize, Debug, Clone)]
+pub struct FeatureContribution {
+    pub feature: String,
+    pub class_label: usize,
+    pub weight: f64,
+    pub value: f64,
+    // weight * value, i.e., this feature's share of the class's log potential
+    pub contribution: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExplainStatistics {
+    pub probability: f64,
+    pub contributions: Vec<FeatureContribution>,
+}
+
+// Hard evidence, stored as a probability, clamps a proposition: inference overwrites both its pi
+// and its lambda with `[1 - p, p]`. Virtual evidence, stored as a likelihood ratio
+// P(observation | true) / P(observation | false), multiplies into the proposition's lambda while
+// its pi keeps coming from its premises. Hard evidence takes precedence over virtual evidence.
 pub trait BeliefTable {
     fn get_proposition_probability(
         &self,
@@ -35,6 +57,27 @@ pub trait BeliefTable {
         }
         Ok(())
     }
+
+    fn get_likelihood_ratio(&self, _proposition: &Proposition) -> Result<Option<f64>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    fn store_likelihood_ratio(&self, proposition: &Proposition, _ratio: f64) -> Result<(), Box<dyn Error>> {
+        Err(format!("This belief table cannot store virtual evidence for {:?}.", proposition).into())
+    }
+}
+
+// Virtual evidence has to leave both values possible; certainty is hard evidence.
+pub fn check_likelihood_ratio(proposition: &Proposition, ratio: f64) -> Result<(), Box<dyn Error>> {
+    if ratio > 0f64 && ratio.is_finite() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Likelihood ratio {} for {:?} must be positive and finite; use hard evidence for certainty.",
+            ratio, proposition
+        )
+        .into())
+    }
 }
 
 pub trait ScenarioMaker {
@@ -0,0 +1,254 @@
+use redis::{ErrorKind, RedisError, RedisResult, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+// An in-process stand-in for the Redis server, so the stores can run without one, as in tests.
+// It understands only the commands this crate sends. Hashes and sets are ordered, so reading
+// them back is deterministic.
+#[derive(Default)]
+pub struct MemoryRedis {
+    strings: HashMap<String, String>,
+    hashes: HashMap<String, BTreeMap<String, String>>,
+    sets: HashMap<String, BTreeSet<String>>,
+    lists: HashMap<String, VecDeque<String>>,
+}
+
+fn unsupported(detail: String) -> RedisError {
+    RedisError::from((ErrorKind::ResponseError, "unsupported command", detail))
+}
+
+fn parse_error() -> RedisError {
+    RedisError::from((ErrorKind::ResponseError, "malformed packed command"))
+}
+
+fn read_line<'a>(bytes: &'a [u8], position: &mut usize) -> RedisResult<&'a [u8]> {
+    let start = *position;
+    let length = bytes[start..]
+        .windows(2)
+        .position(|pair| pair == b"\r\n")
+        .ok_or_else(parse_error)?;
+    *position = start + length + 2;
+    Ok(&bytes[start..start + length])
+}
+
+fn read_count(bytes: &[u8], position: &mut usize, marker: u8) -> RedisResult<usize> {
+    let line = read_line(bytes, position)?;
+    if line.first() != Some(&marker) {
+        return Err(parse_error());
+    }
+    std::str::from_utf8(&line[1..])
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .ok_or_else(parse_error)
+}
+
+// Splits packed commands, each an array of bulk strings, into their arguments.
+fn parse_commands(bytes: &[u8]) -> RedisResult<Vec<Vec<String>>> {
+    let mut position = 0;
+    let mut commands = vec![];
+    while position < bytes.len() {
+        let count = read_count(bytes, &mut position, b'*')?;
+        let mut args = vec![];
+        for _ in 0..count {
+            let length = read_count(bytes, &mut position, b'$')?;
+            let arg = bytes.get(position..position + length).ok_or_else(parse_error)?;
+            if bytes.get(position + length..position + length + 2) != Some(&b"\r\n"[..]) {
+                return Err(parse_error());
+            }
+            args.push(String::from_utf8(arg.to_vec()).map_err(|_| parse_error())?);
+            position += length + 2;
+        }
+        commands.push(args);
+    }
+    Ok(commands)
+}
+
+fn data(value: &str) -> Value {
+    Value::Data(value.as_bytes().to_vec())
+}
+
+fn optional_data(value: Option<&String>) -> Value {
+    value.map_or(Value::Nil, |value| data(value))
+}
+
+fn bulk<'a>(values: impl Iterator<Item = &'a String>) -> Value {
+    Value::Bulk(values.map(|value| data(value)).collect())
+}
+
+// Resolves Redis's inclusive, possibly negative, LRANGE bounds against a list of `length`.
+fn list_range(start: i64, stop: i64, length: usize) -> std::ops::Range<usize> {
+    let length = length as i64;
+    let resolve = |index: i64| if index < 0 { length + index } else { index };
+    let start = resolve(start).max(0);
+    let stop = resolve(stop).min(length - 1);
+    if start > stop {
+        0..0
+    } else {
+        start as usize..stop as usize + 1
+    }
+}
+
+impl MemoryRedis {
+    pub fn execute_packed(&mut self, bytes: &[u8]) -> RedisResult<Vec<Value>> {
+        parse_commands(bytes)?
+            .iter()
+            .map(|args| self.execute(args))
+            .collect()
+    }
+
+    fn execute(&mut self, args: &[String]) -> RedisResult<Value> {
+        let name = args.first().ok_or_else(parse_error)?.to_uppercase();
+        let integer = |index: usize| -> RedisResult<i64> {
+            args.get(index)
+                .and_then(|arg| arg.parse().ok())
+                .ok_or_else(parse_error)
+        };
+        let key = || args.get(1).cloned().ok_or_else(parse_error);
+        match name.as_str() {
+            "PING" => Ok(Value::Status("PONG".to_string())),
+            "FLUSHDB" | "FLUSHALL" => {
+                *self = MemoryRedis::default();
+                Ok(Value::Okay)
+            }
+            "DEL" => {
+                let mut removed = 0;
+                for key in &args[1..] {
+                    let found = self.strings.remove(key).is_some()
+                        | self.hashes.remove(key).is_some()
+                        | self.sets.remove(key).is_some()
+                        | self.lists.remove(key).is_some();
+                    removed += found as i64;
+                }
+                Ok(Value::Int(removed))
+            }
+            "GET" => Ok(optional_data(self.strings.get(&key()?))),
+            "SET" => {
+                let value = args.get(2).ok_or_else(parse_error)?;
+                self.strings.insert(key()?, value.clone());
+                Ok(Value::Okay)
+            }
+            "INCR" | "INCRBY" => {
+                let step = if name == "INCR" { 1 } else { integer(2)? };
+                let entry = self.strings.entry(key()?).or_insert_with(|| "0".to_string());
+                let value = entry.parse::<i64>().map_err(|_| parse_error())? + step;
+                *entry = value.to_string();
+                Ok(Value::Int(value))
+            }
+            "HSET" => {
+                let hash = self.hashes.entry(key()?).or_default();
+                let mut added = 0;
+                for pair in args[2..].chunks(2) {
+                    let [field, value] = pair else { return Err(parse_error()) };
+                    added += hash.insert(field.clone(), value.clone()).is_none() as i64;
+                }
+                Ok(Value::Int(added))
+            }
+            "HSETNX" => {
+                let (field, value) = (args.get(2).ok_or_else(parse_error)?, args.get(3).ok_or_else(parse_error)?);
+                let hash = self.hashes.entry(key()?).or_default();
+                if hash.contains_key(field) {
+                    return Ok(Value::Int(0));
+                }
+                hash.insert(field.clone(), value.clone());
+                Ok(Value::Int(1))
+            }
+            "HGET" => {
+                let field = args.get(2).ok_or_else(parse_error)?;
+                Ok(optional_data(self.hashes.get(&key()?).and_then(|hash| hash.get(field))))
+            }
+            "HGETALL" => Ok(bulk(
+                self.hashes
+                    .get(&key()?)
+                    .into_iter()
+                    .flat_map(|hash| hash.iter().flat_map(|(field, value)| [field, value])),
+            )),
+            "SADD" => {
+                let set = self.sets.entry(key()?).or_default();
+                let added = args[2..].iter().filter(|member| set.insert((*member).clone())).count();
+                Ok(Value::Int(added as i64))
+            }
+            "SMEMBERS" => Ok(bulk(self.sets.get(&key()?).into_iter().flatten())),
+            "RPUSH" => {
+                let list = self.lists.entry(key()?).or_default();
+                list.extend(args[2..].iter().cloned());
+                Ok(Value::Int(list.len() as i64))
+            }
+            "LPOP" => Ok(self
+                .lists
+                .get_mut(&key()?)
+                .and_then(|list| list.pop_front())
+                .map_or(Value::Nil, |value| data(&value))),
+            "LRANGE" => {
+                let (start, stop) = (integer(2)?, integer(3)?);
+                Ok(match self.lists.get(&key()?) {
+                    Some(list) => bulk(list.range(list_range(start, stop, list.len()))),
+                    None => Value::Bulk(vec![]),
+                })
+            }
+            _ => Err(unsupported(name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::Cmd;
+
+    fn run(store: &mut MemoryRedis, command: &Cmd) -> Value {
+        store.execute_packed(&command.get_packed_command()).unwrap().remove(0)
+    }
+
+    #[test]
+    fn splits_pipelined_commands_into_arguments() {
+        let mut pipeline = redis::pipe();
+        pipeline.cmd("SET").arg("key").arg("two\r\nlines").cmd("GET").arg("key");
+        let commands = parse_commands(&pipeline.get_packed_pipeline()).unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                vec!["SET".to_string(), "key".to_string(), "two\r\nlines".to_string()],
+                vec!["GET".to_string(), "key".to_string()],
+            ]
+        );
+        assert!(parse_commands(b"").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_packed_commands() {
+        for bytes in [
+            &b"*1\r\n$4\r\nPING"[..],
+            &b"*1\r\n$9\r\nPING\r\n"[..],
+            &b"+1\r\n$4\r\nPING\r\n"[..],
+            &b"*x\r\n"[..],
+            &b"*1\r\n:4\r\nPING\r\n"[..],
+        ] {
+            assert!(parse_commands(bytes).is_err(), "{:?}", String::from_utf8_lossy(bytes));
+        }
+        let mut store = MemoryRedis::default();
+        assert!(store.execute_packed(&redis::cmd("EVAL").arg("return 1").get_packed_command()).is_err());
+    }
+
+    #[test]
+    fn list_ranges_resolve_like_lrange() {
+        assert_eq!(list_range(0, -1, 3), 0..3);
+        assert_eq!(list_range(-2, 10, 3), 1..3);
+        assert_eq!(list_range(2, 1, 3), 0..0);
+        assert_eq!(list_range(0, -1, 0), 0..0);
+    }
+
+    #[test]
+    fn answers_packed_commands_like_the_server() {
+        let mut store = MemoryRedis::default();
+        run(&mut store, redis::cmd("RPUSH").arg("list").arg("a").arg("b").arg("c"));
+        assert_eq!(
+            run(&mut store, redis::cmd("LRANGE").arg("list").arg(1).arg(-1)),
+            Value::Bulk(vec![data("b"), data("c")])
+        );
+        assert_eq!(run(&mut store, redis::cmd("HSETNX").arg("hash").arg("f").arg("1")), Value::Int(1));
+        assert_eq!(run(&mut store, redis::cmd("HSETNX").arg("hash").arg("f").arg("2")), Value::Int(0));
+        assert_eq!(run(&mut store, redis::cmd("HGET").arg("hash").arg("f")), data("1"));
+        assert_eq!(run(&mut store, redis::cmd("INCR").arg("counter")), Value::Int(1));
+        assert_eq!(run(&mut store, redis::cmd("DEL").arg("hash").arg("missing")), Value::Int(1));
+        assert_eq!(run(&mut store, redis::cmd("HGET").arg("hash").arg("f")), Value::Nil);
+    }
+}
@@ -1,10 +1,15 @@
 pub mod redis;
+pub mod memory_redis;
 pub mod interface;
 pub mod model;
 pub mod graph;
 pub mod proposition_db;
 pub mod train;
+pub mod discover;
 pub mod resources;
 pub mod setup;
 pub mod test;
-pub mod logging;
\ No newline at end of file
+pub mod logging;
+pub mod random;
+#[cfg(test)]
+pub mod fixtures;
@@ -4,6 +4,7 @@ use crate::{
     model::{
         self,
         exponential::ExponentialModel,
+        priors::PredicatePriors,
         objects::{
             Domain, Entity, Predicate, PredicateGroup, PredicateFactor, Proposition,
         },
@@ -15,7 +16,7 @@ use std::{cell::RefCell, collections::HashMap, error::Error, rc::Rc};
 use super::{
     proposition_db::RedisBeliefTable,
     graph::InferenceGraph,
-    interface::{PredictStatistics, TrainStatistics},
+    interface::{ExplainStatistics, PredictStatistics, TrainStatistics},
     redis::RedisManager,
     resources::FactoryResources,
 };
@@ -23,15 +24,18 @@ use super::{
 pub struct InferenceModel {
     pub graph: Rc<InferenceGraph>,
     pub model: Rc<dyn FactorModel>,
+    pub priors: Rc<PredicatePriors>,
 }
 
 impl InferenceModel {
     pub fn new_shared(resources: &FactoryResources) -> Result<Rc<Self>, Box<dyn Error>> {
         let graph = InferenceGraph::new_shared(resources)?;
-        let model = ExponentialModel::new_shared(&resources)?;
+        let model = ExponentialModel::new_shared(resources)?;
+        let priors = PredicatePriors::new_shared(resources)?;
         Ok(Rc::new(InferenceModel {
             graph,
             model,
+            priors,
         }))
     }
 }
@@ -53,4 +57,10 @@ pub trait FactorModel {
         probability: f64,
     ) -> Result<TrainStatistics, Box<dyn Error>>;
     fn predict(&self, factor: &FactorContext) -> Result<PredictStatistics, Box<dyn Error>>;
+    fn explain(&self, factor: &FactorContext) -> Result<ExplainStatistics, Box<dyn Error>>;
+    // Changes whenever the parameters change, so inference can cache `predict` until then.
+    // Models that return None are never cached.
+    fn version(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(None)
+    }
 }
@@ -1,5 +1,5 @@
 use crate::{
-    common::interface::BeliefTable,
+    common::interface::{check_likelihood_ratio, BeliefTable},
     inference::table::PropositionNode,
     model::{
         self,
@@ -10,7 +10,8 @@ use crate::{
         },
     },
 };
-use redis::{Commands, Connection};
+use redis::Commands;
+use crate::common::redis::RedisConnection;
 use std::{cell::RefCell, collections::HashMap, error::Error, io::Empty, rc::Rc};
 
 use super::{
@@ -20,17 +21,45 @@ use super::{
 };
 
 pub struct RedisBeliefTable {
-    redis_connection: RefCell<redis::Connection>,
+    redis_connection: RefCell<RedisConnection>,
+    // The Redis hashes holding probabilities and likelihood ratios.
+    probability_key: &'static str,
+    likelihood_ratio_key: &'static str,
 }
 
 impl RedisBeliefTable {
     pub fn new_mutable(client: &RedisManager) -> Result<Box<dyn BeliefTable>, Box<dyn Error>> {
         let redis_connection = client.get_connection()?;
-        Ok(Box::new(RedisBeliefTable { redis_connection }))
+        Ok(Box::new(RedisBeliefTable {
+            redis_connection,
+            probability_key: "probs",
+            likelihood_ratio_key: "likelihood_ratios",
+        }))
     }
     pub fn new_shared(client: &RedisManager) -> Result<Rc<dyn BeliefTable>, Box<dyn Error>> {
         let redis_connection = client.get_connection()?;
-        Ok(Rc::new(RedisBeliefTable { redis_connection }))
+        Ok(Rc::new(RedisBeliefTable {
+            redis_connection,
+            probability_key: "probs",
+            likelihood_ratio_key: "likelihood_ratios",
+        }))
+    }
+    // Beliefs derived by forward chaining, kept apart from the observed facts.
+    pub fn new_derived_shared(client: &RedisManager) -> Result<Rc<dyn BeliefTable>, Box<dyn Error>> {
+        let redis_connection = client.get_connection()?;
+        Ok(Rc::new(RedisBeliefTable {
+            redis_connection,
+            probability_key: "derived_probs",
+            likelihood_ratio_key: "derived_likelihood_ratios",
+        }))
+    }
+    // Empties the derived-belief store, so a new forward run leaves nothing stale behind.
+    pub fn clear_derived(client: &RedisManager) -> Result<(), Box<dyn Error>> {
+        client
+            .get_connection()?
+            .borrow_mut()
+            .del::<_, ()>(&["derived_probs", "derived_likelihood_ratios"])?;
+        Ok(())
     }
 }
 
@@ -40,7 +69,7 @@ impl BeliefTable for RedisBeliefTable {
         &self,
         proposition: &Proposition,
     ) -> Result<Option<f64>, Box<dyn Error>> {
-        if proposition.predicate.function == EXISTENCE_FUNCTION.to_string() {
+        if proposition.predicate.function == EXISTENCE_FUNCTION {
             return Ok(Some(1f64));
         }
         let hash_string = proposition.predicate.hash_string();
@@ -49,7 +78,7 @@ impl BeliefTable for RedisBeliefTable {
         match self
             .redis_connection
             .borrow_mut()
-            .hget::<_, _, String>("probs", &hash_string)
+            .hget::<_, _, String>(self.probability_key, &hash_string)
         {
             Ok(probability_str) => {
                 // Found the entry, parse it
@@ -87,7 +116,7 @@ impl BeliefTable for RedisBeliefTable {
         if let Err(e) = self
             .redis_connection
             .borrow_mut()
-            .hset::<&str, &str, String, bool>("probs", &hash_string, probability.to_string())
+            .hset::<&str, &str, String, bool>(self.probability_key, &hash_string, probability.to_string())
         {
             trace!(
                 "GraphicalModel::store_proposition_probability - Error storing probability in Redis: {}",
@@ -99,6 +128,27 @@ impl BeliefTable for RedisBeliefTable {
         trace!("GraphicalModel::store_proposition_probability - Completed successfully");
         Ok(())
     }
+
+    fn get_likelihood_ratio(&self, proposition: &Proposition) -> Result<Option<f64>, Box<dyn Error>> {
+        let hash_string = proposition.predicate.hash_string();
+        let ratio: Option<String> = self
+            .redis_connection
+            .borrow_mut()
+            .hget(self.likelihood_ratio_key, &hash_string)?;
+        match ratio {
+            Some(ratio) => Ok(Some(ratio.parse::<f64>()?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store_likelihood_ratio(&self, proposition: &Proposition, ratio: f64) -> Result<(), Box<dyn Error>> {
+        check_likelihood_ratio(proposition, ratio)?;
+        let hash_string = proposition.predicate.hash_string();
+        self.redis_connection
+            .borrow_mut()
+            .hset::<&str, &str, String, bool>(self.likelihood_ratio_key, &hash_string, ratio.to_string())?;
+        Ok(())
+    }
 }
 
 pub struct EmptyBeliefTable;
@@ -116,7 +166,7 @@ impl BeliefTable for EmptyBeliefTable {
         &self,
         proposition: &Proposition,
     ) -> Result<Option<f64>, Box<dyn Error>> {
-        if proposition.predicate.function == EXISTENCE_FUNCTION.to_string() {
+        if proposition.predicate.function == EXISTENCE_FUNCTION {
             return Ok(Some(1f64));
         }
         Ok(None)
@@ -133,17 +183,21 @@ impl BeliefTable for EmptyBeliefTable {
 
 pub struct HashMapBeliefTable {
     evidence: RefCell<HashMap<PropositionNode, f64>>,
+    likelihood_ratios: RefCell<HashMap<PropositionNode, f64>>,
 }
 
 impl HashMapBeliefTable {
     pub fn new() -> Rc<HashMapBeliefTable> {
         Rc::new(HashMapBeliefTable {
             evidence: RefCell::new(HashMap::new()),
+            likelihood_ratios: RefCell::new(HashMap::new()),
         })
     }
 
-    pub fn clear(&self, node:&PropositionNode) -> () {
+    // Removes both hard and virtual evidence.
+    pub fn clear(&self, node:&PropositionNode) {
         self.evidence.borrow_mut().remove(node);
+        self.likelihood_ratios.borrow_mut().remove(node);
     }
 }
 
@@ -152,7 +206,7 @@ impl BeliefTable for HashMapBeliefTable {
         &self,
         proposition: &Proposition,
     ) -> Result<Option<f64>, Box<dyn Error>> {
-        if proposition.predicate.function == EXISTENCE_FUNCTION.to_string() {
+        if proposition.predicate.function == EXISTENCE_FUNCTION {
             return Ok(Some(1f64));
         }
         let node = PropositionNode::from_single(proposition);
@@ -171,4 +225,16 @@ impl BeliefTable for HashMapBeliefTable {
         self.evidence.borrow_mut().insert(node, probability);
         Ok(())
     }
+
+    fn get_likelihood_ratio(&self, proposition: &Proposition) -> Result<Option<f64>, Box<dyn Error>> {
+        let node = PropositionNode::from_single(proposition);
+        Ok(self.likelihood_ratios.borrow().get(&node).copied())
+    }
+
+    fn store_likelihood_ratio(&self, proposition: &Proposition, ratio: f64) -> Result<(), Box<dyn Error>> {
+        check_likelihood_ratio(proposition, ratio)?;
+        let node = PropositionNode::from_single(proposition);
+        self.likelihood_ratios.borrow_mut().insert(node, ratio);
+        Ok(())
+    }
 }
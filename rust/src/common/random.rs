@@ -0,0 +1,62 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    static GLOBAL_RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+// Re-seeds the generator shared by data generation, weight initialization and sampling.
+// With `None` the generator is seeded from entropy, so runs are not reproducible.
+pub fn seed_global_rng(seed: Option<u64>) {
+    let rng = match seed {
+        Some(value) => StdRng::seed_from_u64(value),
+        None => StdRng::from_entropy(),
+    };
+    GLOBAL_RNG.with(|cell| *cell.borrow_mut() = rng);
+}
+
+pub fn with_global_rng<T>(f: impl FnOnce(&mut StdRng) -> T) -> T {
+    GLOBAL_RNG.with(|cell| f(&mut cell.borrow_mut()))
+}
+
+pub fn random_f64() -> f64 {
+    with_global_rng(|rng| rng.gen::<f64>())
+}
+
+// A generator that gives the same draws for the same `stream` every time it is made, so that
+// an estimate drawn from it can be repeated. Mixed with `seed` when there is one.
+pub fn stream_rng(seed: Option<u64>, stream: u64) -> StdRng {
+    StdRng::seed_from_u64(seed.unwrap_or(0) ^ stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::weights::random_weight;
+    use crate::scenarios::helpers::weighted_cointoss;
+
+    fn draw_sequence() -> (Vec<f64>, Vec<bool>) {
+        let weights = (0..32).map(|_| random_weight()).collect();
+        let tosses = (0..32).map(|_| weighted_cointoss(0.3f64)).collect();
+        (weights, tosses)
+    }
+
+    #[test]
+    fn same_seed_gives_same_draws() {
+        seed_global_rng(Some(17));
+        let first = draw_sequence();
+        seed_global_rng(Some(17));
+        let second = draw_sequence();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn different_seeds_give_different_draws() {
+        seed_global_rng(Some(17));
+        let first = draw_sequence();
+        seed_global_rng(Some(18));
+        let second = draw_sequence();
+        assert_ne!(first, second);
+    }
+}
@@ -1,49 +1,114 @@
-use redis::Commands;
-use redis::Connection;
+use super::memory_redis::MemoryRedis;
+use redis::{Commands, ConnectionLike, RedisResult, Value};
 use std::cell::RefCell;
 use std::error::Error;
+use std::rc::Rc;
+
+enum Backend {
+    Server(redis::Client),
+    Memory(Rc<RefCell<MemoryRedis>>),
+}
 
 pub struct RedisManager {
-    client: redis::Client,
+    backend: Backend,
+}
+
+// A connection to the Redis server, or to the in-memory store shared by one `RedisManager`.
+pub enum RedisConnection {
+    Server(redis::Connection),
+    Memory(Rc<RefCell<MemoryRedis>>),
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        match self {
+            RedisConnection::Server(connection) => connection.req_packed_command(cmd),
+            RedisConnection::Memory(store) => Ok(store.borrow_mut().execute_packed(cmd)?.remove(0)),
+        }
+    }
+
+    fn req_packed_commands(&mut self, cmd: &[u8], offset: usize, count: usize) -> RedisResult<Vec<Value>> {
+        match self {
+            RedisConnection::Server(connection) => connection.req_packed_commands(cmd, offset, count),
+            RedisConnection::Memory(store) => {
+                let values = store.borrow_mut().execute_packed(cmd)?;
+                Ok(values.into_iter().skip(offset).take(count).collect())
+            }
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            RedisConnection::Server(connection) => connection.get_db(),
+            RedisConnection::Memory(_) => 0,
+        }
+    }
+
+    fn check_connection(&mut self) -> bool {
+        match self {
+            RedisConnection::Server(connection) => connection.check_connection(),
+            RedisConnection::Memory(_) => true,
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        match self {
+            RedisConnection::Server(connection) => connection.is_open(),
+            RedisConnection::Memory(_) => true,
+        }
+    }
 }
 
 impl RedisManager {
     pub fn new() -> Result<RedisManager, Box<dyn Error>> {
         let client =
             redis::Client::open("redis://127.0.0.1/").expect("Could not connect to Redis."); // Replace with your Redis server URL
-        let redis_client = RedisManager { client };
+        let redis_client = RedisManager {
+            backend: Backend::Server(client),
+        };
         Ok(redis_client)
     }
 
-    pub fn get_connection(&self) -> Result<RefCell<redis::Connection>, Box<dyn Error>> {
-        let connection = self
-            .client
-            .get_connection()
-            .expect("Couldn't get connection.");
+    // Every connection from this manager sees the same in-memory store, which starts empty.
+    pub fn new_in_memory() -> RedisManager {
+        RedisManager {
+            backend: Backend::Memory(Rc::new(RefCell::new(MemoryRedis::default()))),
+        }
+    }
+
+    pub fn get_connection(&self) -> Result<RefCell<RedisConnection>, Box<dyn Error>> {
+        let connection = match &self.backend {
+            Backend::Server(client) => RedisConnection::Server(
+                client
+                    .get_connection()
+                    .expect("Couldn't get connection."),
+            ),
+            Backend::Memory(store) => RedisConnection::Memory(store.clone()),
+        };
         let refcell = RefCell::new(connection);
         Ok(refcell)
     }
 
-    pub fn drop_all_dbs(&mut self) -> Result<(), Box<dyn Error>> {
+    pub fn drop_all_dbs(&self) -> Result<(), Box<dyn Error>> {
         let connection = self.get_connection()?;
-        redis::cmd("FLUSHDB").query(&mut connection.borrow_mut())?;
+        redis::cmd("FLUSHDB").query::<()>(&mut *connection.borrow_mut())?;
         trace!("Database flushed successfully");
         Ok(())
     }
 }
 
 pub fn map_insert(
-    conn: &mut Connection,
+    conn: &mut RedisConnection,
     key: &str,
     field: &str,
     value: &str,
 ) -> Result<(), Box<dyn Error>> {
-    conn.hset(key, field, value)?;
+    conn.hset::<_, _, _, ()>(key, field, value)?;
     Ok(())
 }
 
 pub fn map_get(
-    conn: &mut Connection,
+    conn: &mut RedisConnection,
     key: &str,
     field: &str,
 ) -> Result<Option<String>, Box<dyn Error>> {
@@ -51,27 +116,27 @@ pub fn map_get(
     Ok(value)
 }
 
-pub fn set_add(conn: &mut Connection, key: &str, member: &str) -> Result<bool, Box<dyn Error>> {
+pub fn set_add(conn: &mut RedisConnection, key: &str, member: &str) -> Result<bool, Box<dyn Error>> {
     let added: bool = conn.sadd(key, member)?;
     Ok(added)
 }
 
-pub fn set_members(conn: &mut Connection, key: &str) -> Result<Vec<String>, Box<dyn Error>> {
+pub fn set_members(conn: &mut RedisConnection, key: &str) -> Result<Vec<String>, Box<dyn Error>> {
     let members: Vec<String> = conn.smembers(key)?;
     Ok(members)
 }
 
-pub fn seq_push(conn: &mut Connection, key: &str, value: &str) -> Result<i64, Box<dyn Error>> {
+pub fn seq_push(conn: &mut RedisConnection, key: &str, value: &str) -> Result<i64, Box<dyn Error>> {
     let length: i64 = conn.rpush(key, value)?;
     Ok(length)
 }
 
-pub fn seq_pop(conn: &mut Connection, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+pub fn seq_pop(conn: &mut RedisConnection, key: &str) -> Result<Option<String>, Box<dyn Error>> {
     let value: Option<String> = conn.lpop(key, None)?;
     Ok(value)
 }
 
-pub fn seq_get_all(conn: &mut Connection, key: &str) -> Result<Vec<String>, Box<dyn Error>> {
+pub fn seq_get_all(conn: &mut RedisConnection, key: &str) -> Result<Vec<String>, Box<dyn Error>> {
     let elements: Vec<String> = conn.lrange(key, 0, -1)?;
     Ok(elements)
 }
@@ -1,5 +1,5 @@
 use std::error::Error;
-use super::{redis::RedisManager, setup::ConfigurationOptions};
+use super::{random::seed_global_rng, redis::RedisManager, setup::ConfigurationOptions};
 
 pub struct FactoryResources {
     pub config: ConfigurationOptions,
@@ -8,9 +8,19 @@ pub struct FactoryResources {
 
 impl FactoryResources {
     pub fn new(options: &ConfigurationOptions) -> Result<FactoryResources, Box<dyn Error>> {
+        seed_global_rng(options.seed);
         Ok(FactoryResources {
             config: options.clone(),
             redis: RedisManager::new()?,
         })
     }
+
+    // Resources backed by an empty in-memory store instead of the Redis server.
+    pub fn new_in_memory(options: &ConfigurationOptions) -> FactoryResources {
+        seed_global_rng(options.seed);
+        FactoryResources {
+            config: options.clone(),
+            redis: RedisManager::new_in_memory(),
+        }
+    }
 }
@@ -3,7 +3,7 @@ use crate::scenarios::dating_simple::SimpleDating;
 use clap::{App, Arg};
 use env_logger::{Builder, Env};
 use serde::Deserialize;
-use std::{io::Write, path::Path};
+use std::{ffi::OsString, io::Write, path::Path};
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct ConfigurationOptions {
@@ -13,6 +13,30 @@ pub struct ConfigurationOptions {
     pub print_training_loss: bool,
     pub test_example: Option<u32>,
     pub marginal_output_file: Option<String>,
+    pub seed: Option<u64>,
+    pub bias_features: bool,
+    pub interaction_features: bool,
+    pub em_iterations: u32,
+    pub store_discovered_rules: Option<usize>,
+    pub print_json: bool,
+    pub max_iterations: usize,
+    pub tolerance: f64,
+    pub damping: f64,
+    pub gibbs_samples: usize,
+    pub burn_in: usize,
+    pub thinning: usize,
+    pub chains: usize,
+    pub importance_samples: usize,
+    pub max_exact_fan_in: usize,
+    pub fan_in_samples: usize,
+    pub break_cycles: bool,
+    pub max_graph_depth: Option<usize>,
+    pub max_graph_nodes: Option<usize>,
+    pub graph_time_budget_ms: Option<u64>,
+    pub schedule: String,
+    pub store_derived: bool,
+    pub forward_rounds: usize,
+    pub learned_priors: bool,
 }
 
 fn check_file_does_not_exist(file_name: &str) {
@@ -36,6 +60,15 @@ pub fn parse_configuration_options() -> ConfigurationOptions {
             )
         })
         .init();
+    configuration_options_from_args(std::env::args_os())
+}
+
+// Parses options from `args`, whose first item is the program name, as the command line would.
+pub fn configuration_options_from_args<I, T>(args: I) -> ConfigurationOptions
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
     let matches = App::new("BAYES STAR")
         .version("1.0")
         .author("Greg Coppola")
@@ -84,7 +117,183 @@ pub fn parse_configuration_options() -> ConfigurationOptions {
                 .help("Sets the file name for marginal output (optional)")
                 .takes_value(true), // This argument is optional and takes a string value
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("NUMBER")
+                .help("Seeds every random number generator, for reproducible runs (optional)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("bias_features")
+                .long("bias_features")
+                .help("Adds a bias feature for each conclusion function")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("interaction_features")
+                .long("interaction_features")
+                .help("Adds a feature for each pair of premises of a conclusion")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("em_iterations")
+                .long("em_iterations")
+                .value_name("NUMBER")
+                .help("Rounds of expectation-maximization for training examples with unobserved premises")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("store_discovered_rules")
+                .long("store_discovered_rules")
+                .value_name("NUMBER")
+                .help("Stores this many of the best discovered rules (optional)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("print_json")
+                .long("print_json")
+                .help("Prints reports as JSON instead of a table")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("max_iterations")
+                .long("max_iterations")
+                .value_name("NUMBER")
+                .help("Caps the rounds of belief propagation")
+                .takes_value(true)
+                .default_value("50"),
+        )
+        .arg(
+            Arg::with_name("tolerance")
+                .long("tolerance")
+                .value_name("NUMBER")
+                .help("Belief propagation stops once no message changes by more than this")
+                .takes_value(true)
+                .default_value("0.000001"),
+        )
+        .arg(
+            Arg::with_name("damping")
+                .long("damping")
+                .value_name("NUMBER")
+                .help("Weight in [0, 1) kept from the previous message each time a message is sent")
+                .takes_value(true)
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("gibbs_samples")
+                .long("gibbs_samples")
+                .value_name("NUMBER")
+                .help("Samples kept per chain by Gibbs sampling")
+                .takes_value(true)
+                .default_value("1000"),
+        )
+        .arg(
+            Arg::with_name("burn_in")
+                .long("burn_in")
+                .value_name("NUMBER")
+                .help("Sweeps discarded at the start of each Gibbs chain")
+                .takes_value(true)
+                .default_value("100"),
+        )
+        .arg(
+            Arg::with_name("thinning")
+                .long("thinning")
+                .value_name("NUMBER")
+                .help("Sweeps between kept Gibbs samples")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::with_name("chains")
+                .long("chains")
+                .value_name("NUMBER")
+                .help("Independent Gibbs chains, compared for the R-hat diagnostic")
+                .takes_value(true)
+                .default_value("4"),
+        )
+        .arg(
+            Arg::with_name("importance_samples")
+                .long("importance_samples")
+                .value_name("NUMBER")
+                .help("Samples drawn by likelihood weighting")
+                .takes_value(true)
+                .default_value("10000"),
+        )
+        .arg(
+            Arg::with_name("max_exact_fan_in")
+                .long("max_exact_fan_in")
+                .value_name("NUMBER")
+                .help("Above this many premise groups, log-linear factors are approximated by sampling")
+                .takes_value(true)
+                .default_value("12"),
+        )
+        .arg(
+            Arg::with_name("fan_in_samples")
+                .long("fan_in_samples")
+                .value_name("NUMBER")
+                .help("Premise assignments sampled per message for a factor above max_exact_fan_in")
+                .takes_value(true)
+                .default_value("256"),
+        )
+        .arg(
+            Arg::with_name("break_cycles")
+                .long("break_cycles")
+                .help("Leaves out implications that close a cycle instead of failing on them")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("max_graph_depth")
+                .long("max_graph_depth")
+                .value_name("NUMBER")
+                .help("Propositions this many implications from a target are left unexpanded")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_graph_nodes")
+                .long("max_graph_nodes")
+                .value_name("NUMBER")
+                .help("Stops expanding the proposition graph once it has this many nodes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("graph_time_budget_ms")
+                .long("graph_time_budget_ms")
+                .value_name("NUMBER")
+                .help("Stops expanding the proposition graph after this many milliseconds")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("schedule")
+                .long("schedule")
+                .value_name("NAME")
+                .help("Belief propagation message order: full sweeps in BFS order, or largest residual first")
+                .takes_value(true)
+                .possible_values(["sweep", "residual"])
+                .default_value("sweep"),
+        )
+        .arg(
+            Arg::with_name("store_derived")
+                .long("store_derived")
+                .help("Replaces the contents of the derived-belief store with the forward-chained beliefs")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("forward_rounds")
+                .long("forward_rounds")
+                .value_name("NUMBER")
+                .help("Caps the rounds of forward chaining")
+                .takes_value(true)
+                .default_value("50"),
+        )
+        .arg(
+            Arg::with_name("learned_priors")
+                .long("learned_priors")
+                .help("Leaves propositions without premises as roots with learned priors, instead of linking them to existence")
+                .takes_value(false),
+        )
+        .get_matches_from(args);
     let entities_per_domain: i32 = matches
         .value_of("entities_per_domain")
         .unwrap() // safe because we have a default value
@@ -101,6 +310,99 @@ pub fn parse_configuration_options() -> ConfigurationOptions {
         .expect("scenario_name is required") // As it's required, unwrap directly
         .to_string();
     let test_scenario = matches.value_of("test_scenario").map(String::from);
+    let seed: Option<u64> = matches.value_of("seed").map(|v| {
+        v.parse()
+            .expect("seed needs to be a positive integer or omitted")
+    });
+
+    let em_iterations: u32 = matches
+        .value_of("em_iterations")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("em_iterations needs to be a non-negative integer");
+    let store_discovered_rules: Option<usize> = matches.value_of("store_discovered_rules").map(|v| {
+        v.parse()
+            .expect("store_discovered_rules needs to be a positive integer or omitted")
+    });
+    let print_json = matches.is_present("print_json");
+    let max_iterations: usize = matches
+        .value_of("max_iterations")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("max_iterations needs to be a positive integer");
+    let tolerance: f64 = matches
+        .value_of("tolerance")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("tolerance needs to be a number");
+    let damping: f64 = matches
+        .value_of("damping")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("damping needs to be a number");
+    assert!((0f64..1f64).contains(&damping), "damping needs to be in [0, 1)");
+    let gibbs_samples: usize = matches
+        .value_of("gibbs_samples")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("gibbs_samples needs to be a positive integer");
+    assert!(gibbs_samples > 0, "gibbs_samples needs to be a positive integer");
+    let burn_in: usize = matches
+        .value_of("burn_in")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("burn_in needs to be a non-negative integer");
+    let thinning: usize = matches
+        .value_of("thinning")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("thinning needs to be a positive integer");
+    assert!(thinning > 0, "thinning needs to be a positive integer");
+    let chains: usize = matches
+        .value_of("chains")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("chains needs to be a positive integer");
+    assert!(chains > 0, "chains needs to be a positive integer");
+    let importance_samples: usize = matches
+        .value_of("importance_samples")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("importance_samples needs to be a positive integer");
+    let max_exact_fan_in: usize = matches
+        .value_of("max_exact_fan_in")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("max_exact_fan_in needs to be a non-negative integer");
+    let fan_in_samples: usize = matches
+        .value_of("fan_in_samples")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("fan_in_samples needs to be a positive integer");
+    assert!(fan_in_samples > 0, "fan_in_samples needs to be a positive integer");
+    let break_cycles = matches.is_present("break_cycles");
+    let max_graph_depth: Option<usize> = matches.value_of("max_graph_depth").map(|v| {
+        v.parse()
+            .expect("max_graph_depth needs to be a non-negative integer or omitted")
+    });
+    let max_graph_nodes: Option<usize> = matches.value_of("max_graph_nodes").map(|v| {
+        v.parse()
+            .expect("max_graph_nodes needs to be a non-negative integer or omitted")
+    });
+    let schedule = matches.value_of("schedule").unwrap().to_string();
+    let store_derived = matches.is_present("store_derived");
+    let forward_rounds: usize = matches
+        .value_of("forward_rounds")
+        .unwrap() // safe because we have a default value
+        .parse()
+        .expect("forward_rounds needs to be a positive integer");
+    let learned_priors = matches.is_present("learned_priors");
+    let graph_time_budget_ms: Option<u64> = matches.value_of("graph_time_budget_ms").map(|v| {
+        v.parse()
+            .expect("graph_time_budget_ms needs to be a non-negative integer or omitted")
+    });
+    let bias_features = matches.is_present("bias_features");
+    let interaction_features = matches.is_present("interaction_features");
 
     ConfigurationOptions {
         scenario_name,
@@ -109,5 +411,29 @@ pub fn parse_configuration_options() -> ConfigurationOptions {
         print_training_loss,
         test_example,
         marginal_output_file,
+        seed,
+        bias_features,
+        interaction_features,
+        em_iterations,
+        store_discovered_rules,
+        print_json,
+        max_iterations,
+        tolerance,
+        damping,
+        gibbs_samples,
+        burn_in,
+        thinning,
+        chains,
+        importance_samples,
+        max_exact_fan_in,
+        fan_in_samples,
+        break_cycles,
+        max_graph_depth,
+        max_graph_nodes,
+        graph_time_budget_ms,
+        schedule,
+        store_derived,
+        forward_rounds,
+        learned_priors,
     }
 }
@@ -10,8 +10,8 @@ use crate::{
         train::TrainingPlan,
     },
     inference::{
-        graph::PropositionGraph,
-        inference::Inferencer,
+        graph::{GraphBuildOptions, PropositionGraph},
+        inferencer::Inferencer,
         table::{self, PropositionNode},
     },
     model::{
@@ -47,7 +47,7 @@ impl ReplState {
         loop {
             self.print_menu_options()?;
             let tokens = get_input_tokens_from_user();
-            if tokens.len() < 1 {
+            if tokens.is_empty() {
                 continue;
             }
             let function = &tokens[0];
@@ -58,6 +58,15 @@ impl ReplState {
                 "u" => {
                     self.handle_unset(&tokens);
                 }
+                "v" => {
+                    self.handle_virtual(&tokens)?;
+                }
+                "do" => {
+                    self.handle_do(&tokens)?;
+                }
+                "undo" => {
+                    self.handle_undo(&tokens)?;
+                }
                 "reinit" => {
                     self.inferencer.initialize_chart()?;
                 }
@@ -65,16 +74,22 @@ impl ReplState {
                     self.print_table(&tokens);
                 }
                 "pv" => {
-                    self.inferencer.data.print_table(&function);
+                    self.inferencer.data.print_table(function);
                 }
                 "lv" => {
-                    self.inferencer.data.print_table(&function);
+                    self.inferencer.data.print_table(function);
                 }
                 "pm" => {
-                    self.inferencer.data.print_table(&function);
+                    self.inferencer.data.print_table(function);
                 }
                 "lm" => {
-                    self.inferencer.data.print_table(&function);
+                    self.inferencer.data.print_table(function);
+                }
+                "explain" => {
+                    self.handle_explain(&tokens)?;
+                }
+                "priors" => {
+                    self.print_priors()?;
                 }
                 "m" => {
                     self.inferencer.update_marginals()?;
@@ -83,6 +98,28 @@ impl ReplState {
                     self.inferencer.do_full_forward_and_backward()?;
                     self.inferencer.update_marginals()?;
                 }
+                "bp" => {
+                    let report = self
+                        .inferencer
+                        .propagate_until_converged(None, &mut |_inferencer| Ok(()))?;
+                    println!(
+                        "{:?} iterations {} message updates {} residual {} converged {}",
+                        report.schedule, report.iterations, report.message_updates, report.residual, report.converged
+                    );
+                    self.inferencer.update_marginals()?;
+                }
+                "exact" => {
+                    self.print_exact_marginals()?;
+                }
+                "gibbs" => {
+                    self.print_gibbs_marginals()?;
+                }
+                "lw" => {
+                    self.print_likelihood_weighting_marginals()?;
+                }
+                "mpe" => {
+                    self.print_most_probable_explanation()?;
+                }
                 "q" => break,
                 _ => println!("Command not recognized."),
             };
@@ -90,23 +127,25 @@ impl ReplState {
         Ok(())
     }
 
+    // Stores each named proposition's probability, then brings the chart up to date in one
+    // incremental pass. Returns the first node set, for plots that fan out from it.
     pub fn set_pairs_by_name(&mut self, pairs:&Vec<(&str, f64)>) -> Option<PropositionNode> {
-        assert!(pairs.len() <= 1);
-        for pair in pairs {
-            let key = pair.0.to_string();
-            let node = self.proposition_index.get(&key).unwrap();
-            let prop = node.extract_single();
-            info!("setting {} to {}", &key, pair.1);
+        let mut changed = vec![];
+        for (name, probability) in pairs {
+            let node = self.proposition_index.get(*name).unwrap().clone();
+            info!("setting {} to {}", name, probability);
             self.fact_memory
-                .store_proposition_probability(&prop, pair.1)
+                .store_proposition_probability(&node.extract_single(), *probability)
                 .unwrap();
-            self.inferencer.do_fan_out_from_node(&node).unwrap();
-            return Some(node.clone());
+            changed.push(node);
+        }
+        if !changed.is_empty() {
+            self.inferencer.propagate_from_changes(&changed).unwrap();
         }
-        None
+        changed.into_iter().next()
     }
 
-    fn handle_set(&mut self, tokens: &Vec<String>) {
+    fn handle_set(&mut self, tokens: &[String]) {
         let select_index = tokens[1].parse::<u64>().unwrap();
         let new_prob = tokens[2].parse::<f64>().unwrap();
         let node = self.question_index.get(&select_index).unwrap();
@@ -114,22 +153,156 @@ impl ReplState {
         self.fact_memory
             .store_proposition_probability(&prop, new_prob)
             .unwrap();
-        self.inferencer.do_fan_out_from_node(&node).unwrap();
+        self.inferencer.propagate_from_changes(std::slice::from_ref(node)).unwrap();
         self.inferencer.update_marginals().unwrap();
     }
 
-    fn handle_unset(&mut self, tokens: &Vec<String>) {
+    fn handle_unset(&mut self, tokens: &[String]) {
         let select_index = tokens[1].parse::<u64>().unwrap();
         let node = self.question_index.get(&select_index).unwrap();
         self.fact_memory.clear(node);
-        self.inferencer.do_fan_out_from_node(&node).unwrap();
+        self.inferencer.propagate_from_changes(std::slice::from_ref(node)).unwrap();
     }
 
-    fn print_table(&mut self, tokens: &Vec<String>) {
+    // v <index> <ratio>: virtual evidence with likelihood ratio P(obs | true) / P(obs | false).
+    fn handle_virtual(&mut self, tokens: &[String]) -> Result<(), Box<dyn Error>> {
+        let select_index = tokens.get(1).ok_or("Usage: v <index> <ratio>")?.parse::<u64>()?;
+        let ratio = tokens.get(2).ok_or("Usage: v <index> <ratio>")?.parse::<f64>()?;
+        let node = self.question_index.get(&select_index).ok_or("Index not recognized.")?.clone();
+        self.fact_memory.store_likelihood_ratio(&node.extract_single(), ratio)?;
+        self.inferencer.propagate_from_changes(std::slice::from_ref(&node))?;
+        self.inferencer.update_marginals()?;
+        Ok(())
+    }
+
+    // do <index> <0|1>: forces the proposition and cuts it from its premises.
+    fn handle_do(&mut self, tokens: &[String]) -> Result<(), Box<dyn Error>> {
+        let select_index = tokens.get(1).ok_or("Usage: do <index> <0|1>")?.parse::<u64>()?;
+        let value = tokens.get(2).ok_or("Usage: do <index> <0|1>")?.parse::<u8>()? != 0;
+        let node = self.question_index.get(&select_index).ok_or("Index not recognized.")?.clone();
+        self.inferencer.set_intervention(&node.extract_single(), value)?;
+        self.inferencer.propagate_from_changes(std::slice::from_ref(&node))?;
+        self.inferencer.update_marginals()?;
+        Ok(())
+    }
+
+    fn handle_undo(&mut self, tokens: &[String]) -> Result<(), Box<dyn Error>> {
+        let select_index = tokens.get(1).ok_or("Usage: undo <index>")?.parse::<u64>()?;
+        let node = self.question_index.get(&select_index).ok_or("Index not recognized.")?.clone();
+        self.inferencer.clear_intervention(&node.extract_single());
+        self.inferencer.propagate_from_changes(std::slice::from_ref(&node))?;
+        self.inferencer.update_marginals()?;
+        Ok(())
+    }
+
+    fn print_table(&mut self, tokens: &[String]) {
         let table_name = tokens[1].clone();
         self.inferencer.data.print_table(&table_name);
     }
 
+    fn handle_explain(&self, tokens: &[String]) -> Result<(), Box<dyn Error>> {
+        let select_index = tokens[1].parse::<u64>()?;
+        let node = self.question_index.get(&select_index).ok_or("Index not recognized.")?;
+        let explanation = self.inferencer.explain_conclusion(node)?;
+        println!("EXPLAIN {:?} probability {:.8}", node, explanation.probability);
+        for contribution in &explanation.contributions {
+            println!(
+                "{:<12} {:<6} {:<12} {:<12} {}",
+                format!("{:.8}", contribution.contribution).yellow(),
+                contribution.class_label,
+                format!("{:.8}", contribution.weight),
+                format!("{:.8}", contribution.value),
+                contribution.feature
+            );
+        }
+        Ok(())
+    }
+
+    // Exact marginals beside the current belief propagation marginals.
+    fn print_exact_marginals(&self) -> Result<(), Box<dyn Error>> {
+        let exact = self.inferencer.compute_exact_marginals()?;
+        let approximate = self.inferencer.build_marginal_table()?;
+        println!("EXACT");
+        for (node_string, probability) in exact.entries() {
+            let difference = approximate
+                .get(node_string)
+                .map(|approximate| format!("{:.8}", approximate - probability))
+                .unwrap_or_default();
+            println!(
+                "{:<12} {:<12} {}",
+                format!("{:.8}", probability).green(),
+                difference.yellow(),
+                node_string
+            );
+        }
+        println!("max difference {:.8}", exact.max_difference(&approximate));
+        Ok(())
+    }
+
+    fn print_gibbs_marginals(&self) -> Result<(), Box<dyn Error>> {
+        let report = self.inferencer.gibbs_sample()?;
+        println!("GIBBS");
+        for ((node_string, probability), diagnostics) in
+            report.marginals.entries().iter().zip(&report.diagnostics)
+        {
+            let r_hat = diagnostics
+                .r_hat
+                .map(|r_hat| format!("{:.4}", r_hat))
+                .unwrap_or_default();
+            println!(
+                "{:<12} {:<12} {:<8} {}",
+                format!("{:.8}", probability).green(),
+                format!("{:.8}", diagnostics.standard_error).yellow(),
+                r_hat,
+                node_string
+            );
+        }
+        Ok(())
+    }
+
+    fn print_likelihood_weighting_marginals(&self) -> Result<(), Box<dyn Error>> {
+        let report = self.inferencer.likelihood_weighting(&mut |_report| Ok(true))?;
+        println!("LIKELIHOOD WEIGHTING");
+        for (node_string, probability) in report.marginals.entries() {
+            println!("{:<12} {}", format!("{:.8}", probability).green(), node_string);
+        }
+        println!(
+            "samples {} effective sample size {:.1}",
+            report.samples, report.effective_sample_size
+        );
+        Ok(())
+    }
+
+    fn print_most_probable_explanation(&self) -> Result<(), Box<dyn Error>> {
+        let explanation = self.inferencer.most_probable_explanation()?;
+        println!("MOST PROBABLE EXPLANATION");
+        for (node_string, value) in &explanation.assignment {
+            let value = if *value { "true".green() } else { "false".red() };
+            println!("{:<12} {}", value, node_string);
+        }
+        println!(
+            "probability {:.8} log probability {:.8}",
+            explanation.probability, explanation.log_probability
+        );
+        Ok(())
+    }
+
+    fn print_priors(&self) -> Result<(), Box<dyn Error>> {
+        let mut priors: Vec<(String, f64)> = self
+            .inferencer
+            .model
+            .priors
+            .read_all_priors()?
+            .into_iter()
+            .collect();
+        priors.sort_by(|a, b| a.0.cmp(&b.0));
+        println!("PRIORS");
+        for (function, prior) in &priors {
+            println!("{:<12} {}", format!("{:.8}", prior).green(), function);
+        }
+        Ok(())
+    }
+
     fn print_menu_options(&mut self) -> Result<(), Box<dyn Error>> {
         let bfs = self.inferencer.proposition_graph.get_bfs_order();
         self.question_index.clear();
@@ -138,15 +311,26 @@ impl ReplState {
             if node.is_single() {
                 let single = node.extract_single();
                 let probability = self.fact_memory.get_proposition_probability(&single)?;
-                let probability_string = match &probability {
-                    Some(value) => {
+                let intervention = self.inferencer.intervention_for(node);
+                let probability_string = match (intervention, &probability) {
+                    (Some(value), _) => {
+                        if value {
+                            "do(Yes)".blue()
+                        } else {
+                            "do(No)".blue()
+                        }
+                    }
+                    (None, Some(value)) => {
                         if *value > 0.5f64 {
                             "Yes".green()
                         } else {
                             "No".green()
                         }
                     }
-                    None => "None".yellow(),
+                    (None, None) => match self.fact_memory.get_likelihood_ratio(&single)? {
+                        Some(ratio) => format!("x{}", ratio).cyan(),
+                        None => "None".yellow(),
+                    },
                 };
                 println!("{}\t{}\t{:?}", index, &probability_string, &node);
                 self.question_index.insert(index as u64, node.clone());
@@ -174,16 +358,20 @@ pub fn interactive_inference_example(
     resources: &FactoryResources,
 ) -> Result<(), Box<dyn Error>> {
     let plan = TrainingPlan::new(&resources.redis)?;
-    let graphical_model = InferenceModel::new_shared(&resources)?;
+    let graphical_model = InferenceModel::new_shared(resources)?;
     let plan = TrainingPlan::new(&resources.redis)?;
-    let model = InferenceModel::new_shared(&resources).unwrap();
+    let model = InferenceModel::new_shared(resources).unwrap();
     let test_questions = plan.get_test_questions().unwrap();
     let target = &test_questions[config.test_example.unwrap() as usize];
     let fact_memory = EmptyBeliefTable::new_shared(&resources.redis)?;
-    let proposition_graph = PropositionGraph::new_shared(model.graph.clone(), target)?;
+    let proposition_graph = PropositionGraph::new_shared_for_targets(
+        model.graph.clone(),
+        std::slice::from_ref(target),
+        &GraphBuildOptions::from_config(config),
+    )?;
     proposition_graph.visualize();
     let mut inferencer =
-        Inferencer::new_mutable(&config, model.clone(), proposition_graph.clone(), fact_memory)?;
+        Inferencer::new_mutable(config, model.clone(), proposition_graph.clone(), fact_memory)?;
     inferencer.initialize_chart()?;
     let mut repl = ReplState::new(inferencer);
     repl.do_repl_loop()?;
@@ -195,8 +383,8 @@ pub fn summarize_examples(
     resources: &FactoryResources,
 ) -> Result<(), Box<dyn Error>> {
     let plan = TrainingPlan::new(&resources.redis)?;
-    let graphical_model = InferenceModel::new_shared(&resources)?;
-    let model = InferenceModel::new_shared(&resources).unwrap();
+    let graphical_model = InferenceModel::new_shared(resources)?;
+    let model = InferenceModel::new_shared(resources).unwrap();
     // test
     let test_questions = plan.get_test_questions().unwrap();
     for (index, proposition) in test_questions.iter().enumerate() {
@@ -8,9 +8,10 @@ use crate::{
         },
     }, print_yellow
 };
-use redis::{Commands, Connection};
+use redis::Commands;
+use crate::common::redis::RedisConnection;
 use serde::Deserialize;
-use std::{cell::RefCell, error::Error};
+use std::{cell::RefCell, collections::HashMap, error::Error};
 
 use super::{
     interface::{PredictStatistics, TrainStatistics},
@@ -23,12 +24,16 @@ use super::resources::FactoryResources;
 use crate::common::proposition_db::RedisBeliefTable;
 use crate::common::model::InferenceModel;
 use crate::model::choose::extract_backimplications_from_proposition;
+use crate::model::priors::PredicatePriors;
+use crate::inference::graph::{GraphBuildOptions, PropositionGraph};
+use crate::inference::inferencer::Inferencer;
+use crate::inference::table::PropositionNode;
 use std::borrow::BorrowMut;
 
 
 
 pub struct TrainingPlan {
-    redis_connection: RefCell<redis::Connection>,
+    redis_connection: RefCell<RedisConnection>,
 }
 
 impl TrainingPlan {
@@ -78,7 +83,7 @@ impl TrainingPlan {
         proposition: &Proposition,
     ) -> Result<(), Box<dyn Error>> {
         if is_training {
-            self.add_proposition_to_queue(&"training_queue".to_string(), &proposition)
+            self.add_proposition_to_queue(&"training_queue".to_string(), proposition)
         } else {
             Ok(())
         }
@@ -90,7 +95,7 @@ impl TrainingPlan {
         proposition: &Proposition,
     ) -> Result<(), Box<dyn Error>> {
         if is_test {
-            self.add_proposition_to_queue(&"test_queue".to_string(), &proposition)
+            self.add_proposition_to_queue(&"test_queue".to_string(), proposition)
         } else {
             Ok(())
         }
@@ -104,7 +109,7 @@ impl TrainingPlan {
             "GraphicalModel::get_propositions_from_queue - Start. Queue name: {}",
             seq_name
         );
-        let records = seq_get_all(&mut self.redis_connection.borrow_mut(), &seq_name)?;
+        let records = seq_get_all(&mut self.redis_connection.borrow_mut(), seq_name)?;
         let mut result = vec![];
         for record in &records {
             let proposition = deserialize_record(record)?;
@@ -133,27 +138,43 @@ where
 }
 
 // Probabilities are either 0 or 1, so assume independent, i.e., just boolean combine them as AND.
+// An unobserved term falls back to its expected value from the last E-step.
 fn extract_group_probability_for_training(
-    proposition_db: &Box<dyn BeliefTable>,
+    proposition_db: &dyn BeliefTable,
     premise:&PropositionGroup,
+    expectations: &HashMap<Proposition, f64>,
 ) -> Result<f64, Box<dyn Error>> {
     let mut product = 1f64;
     for term in &premise.terms {
-        let part = proposition_db.get_proposition_probability(term)?.unwrap();
+        let part = match proposition_db.get_proposition_probability(term)? {
+            Some(probability) => probability,
+            None => match expectations.get(term) {
+                Some(expectation) => *expectation,
+                None => {
+                    return Err(format!(
+                        "No probability for premise {}; training with unobserved premises needs --em_iterations.",
+                        term.hash_string()
+                    )
+                    .into())
+                }
+            },
+        };
         product *= part;
     }
     Ok(product)
 }
 
 fn extract_factor_for_proposition_for_training(
-    proposition_db: &Box<dyn BeliefTable>,
+    proposition_db: &dyn BeliefTable,
     graph: &InferenceGraph,
     conclusion: Proposition,
+    expectations: &HashMap<Proposition, f64>,
 ) -> Result<FactorContext, Box<dyn Error>> {
     let factors = extract_backimplications_from_proposition(graph, &conclusion)?;
     let mut probabilities = vec![];
     for factor in &factors {
-        let probability = extract_group_probability_for_training(proposition_db, &factor.premise)?;
+        let probability =
+            extract_group_probability_for_training(proposition_db, &factor.premise, expectations)?;
         probabilities.push(probability);
     }
     let result = FactorContext {
@@ -163,11 +184,71 @@ fn extract_factor_for_proposition_for_training(
     Ok(result)
 }
 
+fn extract_missing_premises_for_training(
+    proposition_db: &dyn BeliefTable,
+    graph: &InferenceGraph,
+    conclusion: &Proposition,
+) -> Result<Vec<Proposition>, Box<dyn Error>> {
+    let mut result = vec![];
+    for factor in extract_backimplications_from_proposition(graph, conclusion)? {
+        for term in &factor.premise.terms {
+            if proposition_db.get_proposition_probability(term)?.is_none() && !result.contains(term) {
+                result.push(term.clone());
+            }
+        }
+    }
+    Ok(result)
+}
+
+// E-step: runs inference under the current weights, with everything in the belief table as
+// evidence, and records the posterior of each unobserved premise. A premise shared by several
+// conclusions gets the average of its posteriors in each of their graphs.
+fn expect_missing_premises(
+    resources: &FactoryResources,
+    proposition_db: &dyn BeliefTable,
+    graph: &InferenceGraph,
+    training_questions: &[Proposition],
+) -> Result<HashMap<Proposition, f64>, Box<dyn Error>> {
+    let model = InferenceModel::new_shared(resources)?;
+    let fact_memory = RedisBeliefTable::new_shared(&resources.redis)?;
+    let mut totals: HashMap<Proposition, (f64, f64)> = HashMap::new();
+    for conclusion in training_questions {
+        let missing = extract_missing_premises_for_training(proposition_db, graph, conclusion)?;
+        if missing.is_empty() {
+            continue;
+        }
+        let proposition_graph = PropositionGraph::new_shared_for_targets(
+            model.graph.clone(),
+            std::slice::from_ref(conclusion),
+            &GraphBuildOptions::from_config(&resources.config),
+        )?;
+        let mut inferencer = Inferencer::new_mutable(
+            &resources.config,
+            model.clone(),
+            proposition_graph,
+            fact_memory.clone(),
+        )?;
+        inferencer.initialize_chart()?;
+        inferencer.propagate_until_converged(None, &mut |_inferencer| Ok(()))?;
+        for term in &missing {
+            let expectation = inferencer.get_marginal(&PropositionNode::from_single(term))?;
+            trace!("expect_missing_premises - {:?} expected {}", term, expectation);
+            let entry = totals.entry(term.clone()).or_insert((0f64, 0f64));
+            entry.0 += expectation;
+            entry.1 += 1f64;
+        }
+    }
+    Ok(totals
+        .into_iter()
+        .map(|(term, (sum, count))| (term, sum / count))
+        .collect())
+}
+
 pub fn do_training(resources: &FactoryResources) -> Result<(), Box<dyn Error>> {
     let graph = InferenceGraph::new_mutable(resources)?;
     let proposition_db = RedisBeliefTable::new_mutable(&resources.redis)?;
     let plan = TrainingPlan::new(&resources.redis)?;
-    let mut factor_model = ExponentialModel::new_mutable(&resources)?;
+    let mut factor_model = ExponentialModel::new_mutable(resources)?;
     trace!("do_training - Getting all implications");
     let implications = graph.get_all_implications()?;
     for implication in implications {
@@ -180,15 +261,32 @@ pub fn do_training(resources: &FactoryResources) -> Result<(), Box<dyn Error>> {
         "do_training - Processing propositions: {}",
         training_questions.len()
     );
-    let mut examples_processed = 0;
+    let priors = PredicatePriors::new_mutable(resources)?;
+    let mut observations = vec![];
     for proposition in &training_questions {
-        trace!("do_training - Processing proposition: {:?}", proposition);
-        let factor = extract_factor_for_proposition_for_training(&proposition_db, &graph, proposition.clone())?;
-        trace!("do_training - Backimplications: {:?}", &factor);
-        let probabiity_opt = proposition_db.get_proposition_probability(proposition)?;
-        let probability = probabiity_opt.expect("Probability should exist.");
-        let _stats = factor_model.train(&factor, probability) ?;
-        examples_processed += 1;
+        if let Some(probability) = proposition_db.get_proposition_probability(proposition)? {
+            observations.push((proposition.clone(), probability));
+        }
+    }
+    priors.learn_from_base_rates(&observations)?;
+    let em_iterations = resources.config.em_iterations;
+    let mut examples_processed = 0;
+    for iteration in 0..em_iterations.max(1) {
+        let expectations = if em_iterations > 0 {
+            print_yellow!("do_training - EM iteration {}", iteration);
+            expect_missing_premises(resources, proposition_db.as_ref(), &graph, &training_questions)?
+        } else {
+            HashMap::new()
+        };
+        for proposition in &training_questions {
+            trace!("do_training - Processing proposition: {:?}", proposition);
+            let factor = extract_factor_for_proposition_for_training(proposition_db.as_ref(), &graph, proposition.clone(), &expectations)?;
+            trace!("do_training - Backimplications: {:?}", &factor);
+            let probabiity_opt = proposition_db.get_proposition_probability(proposition)?;
+            let probability = probabiity_opt.expect("Probability should exist.");
+            let _stats = factor_model.train(&factor, probability) ?;
+            examples_processed += 1;
+        }
     }
     trace!(
         "do_training - Training complete: examples processed {}",
@@ -201,8 +299,7 @@ pub fn setup_and_train(
     resources: &FactoryResources,
     scenario_maker: &dyn ScenarioMaker,
 ) -> Result<(), Box<dyn Error>> {
-    let mut redis_client = RedisManager::new()?;
-    redis_client.drop_all_dbs()?;
+    resources.redis.drop_all_dbs()?;
     let model_spec = "dummy_model_spec".to_string();
     let result = scenario_maker.setup_scenario(resources);
     trace!("scenario result: {:?}", result);
@@ -210,3 +307,32 @@ pub fn setup_and_train(
     trace!("train result: {:?}", train_result);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::{in_memory, jack, rule};
+
+    #[test]
+    fn shared_premise_expectation_averages_its_conclusions() {
+        let (_config, resources) = in_memory(&["--seed", "11"]);
+        let mut graph = InferenceGraph::new_mutable(&resources).unwrap();
+        let mut model = ExponentialModel::new_mutable(&resources).unwrap();
+        for conclusion in ["a", "b"] {
+            let link = rule(&["hidden"], conclusion);
+            graph.store_predicate_implication(&link).unwrap();
+            model.initialize_connection(&link).unwrap();
+        }
+        let facts = RedisBeliefTable::new_mutable(&resources.redis).unwrap();
+        facts.store_proposition_probability(&jack("a"), 1f64).unwrap();
+        facts.store_proposition_probability(&jack("b"), 0f64).unwrap();
+        let expect = |questions: &[Proposition]| {
+            expect_missing_premises(&resources, facts.as_ref(), &graph, questions).unwrap()[&jack("hidden")]
+        };
+        let (from_a, from_b) = (expect(&[jack("a")]), expect(&[jack("b")]));
+        assert!((from_a - from_b).abs() > 1e-6);
+        let both = expect(&[jack("a"), jack("b")]);
+        assert!((both - (from_a + from_b) / 2f64).abs() < 1e-12);
+        assert_eq!(both, expect(&[jack("b"), jack("a")]));
+    }
+}
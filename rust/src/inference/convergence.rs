@@ -0,0 +1,287 @@
+use super::{inferencer::Inferencer, table::PropositionNode};
+use crate::common::setup::ConfigurationOptions;
+use serde::Serialize;
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    error::Error,
+};
+
+pub type IterationCallback<'a> = dyn FnMut(&Inferencer) -> Result<(), Box<dyn Error>> + 'a;
+
+// The order in which `propagate_until_converged` updates messages.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageSchedule {
+    // Full pi and lambda sweeps in `bfs_order`.
+    Sweep,
+    // Residual belief propagation: the node whose incoming messages changed most since it was
+    // last updated goes next.
+    Residual,
+}
+
+impl MessageSchedule {
+    pub fn from_config(config: &ConfigurationOptions) -> MessageSchedule {
+        match config.schedule.as_str() {
+            "residual" => MessageSchedule::Residual,
+            _ => MessageSchedule::Sweep,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ConvergenceReport {
+    // For `MessageSchedule::Residual`, node updates divided by the number of nodes, rounded up.
+    pub iterations: usize,
+    // The largest change of a normalized message not yet propagated.
+    pub residual: f64,
+    pub converged: bool,
+    pub schedule: MessageSchedule,
+    // How many messages were recomputed in total.
+    pub message_updates: usize,
+}
+
+// A node waiting to be updated, ordered by the largest change among the messages it received
+// since its last update. Entries go stale when the priority rises, and are skipped when popped.
+struct Pending {
+    priority: f64,
+    index: usize,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .total_cmp(&other.priority)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl Inferencer {
+    // One round of propagation: a fan out from `focus` if given, else a full pi and lambda sweep.
+    // Each message is damped as it is sent; returns the largest change of one.
+    pub fn propagate_round(&mut self, focus: Option<&PropositionNode>) -> Result<f64, Box<dyn Error>> {
+        self.data.take_largest_change();
+        match focus {
+            Some(node) => self.do_fan_out_from_node(node)?,
+            None => self.do_full_forward_and_backward()?,
+        }
+        Ok(self.data.take_largest_change())
+    }
+
+    // Every pi and every lambda message, i.e. twice the number of edges.
+    pub fn messages_per_sweep(&self) -> usize {
+        self.bfs_order
+            .iter()
+            .map(|node| {
+                self.proposition_graph.get_all_forward(node).len()
+                    + self.proposition_graph.get_all_backward(node).len()
+            })
+            .sum()
+    }
+
+    // Loopy belief propagation under `self.schedule`, until no message changes by more than
+    // `config.tolerance`, or `config.max_iterations` rounds have run. `on_iteration` sees the
+    // chart after each round.
+    pub fn propagate_until_converged(
+        &mut self,
+        focus: Option<&PropositionNode>,
+        on_iteration: &mut IterationCallback,
+    ) -> Result<ConvergenceReport, Box<dyn Error>> {
+        self.refresh_factor_cache()?;
+        match self.schedule {
+            MessageSchedule::Sweep => self.propagate_by_sweeps(focus, on_iteration),
+            MessageSchedule::Residual => self.propagate_by_residuals(focus, on_iteration),
+        }
+    }
+
+    // Repeats `propagate_round`.
+    fn propagate_by_sweeps(
+        &mut self,
+        focus: Option<&PropositionNode>,
+        on_iteration: &mut IterationCallback,
+    ) -> Result<ConvergenceReport, Box<dyn Error>> {
+        let mut residual = f64::INFINITY;
+        let mut iterations = 0;
+        while iterations < self.config.max_iterations {
+            residual = self.propagate_round(focus)?;
+            iterations += 1;
+            on_iteration(self)?;
+            trace!("propagate_until_converged iteration {} residual {}", iterations, residual);
+            if residual <= self.config.tolerance {
+                break;
+            }
+        }
+        let converged = residual <= self.config.tolerance;
+        if !converged {
+            warn!(
+                "belief propagation did not converge: residual {} after {} iterations",
+                residual, iterations
+            );
+        }
+        Ok(ConvergenceReport {
+            iterations,
+            residual,
+            converged,
+            schedule: MessageSchedule::Sweep,
+            message_updates: iterations * self.messages_per_sweep(),
+        })
+    }
+
+    // Starts from `focus` if given, else from every node, and always updates the node with the
+    // largest pending change next, until none exceeds `config.tolerance`. A round here is as
+    // many updates as there are nodes.
+    fn propagate_by_residuals(
+        &mut self,
+        focus: Option<&PropositionNode>,
+        on_iteration: &mut IterationCallback,
+    ) -> Result<ConvergenceReport, Box<dyn Error>> {
+        let nodes = self.bfs_order.clone();
+        let index: HashMap<PropositionNode, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.clone(), i))
+            .collect();
+        let mut priorities = vec![0f64; nodes.len()];
+        let mut heap = BinaryHeap::new();
+        for (i, node) in nodes.iter().enumerate() {
+            if focus.is_none() || focus == Some(node) {
+                priorities[i] = f64::INFINITY;
+                heap.push(Pending {
+                    priority: f64::INFINITY,
+                    index: i,
+                });
+            }
+        }
+        let budget = self.config.max_iterations * nodes.len();
+        let mut updates = 0;
+        let mut message_updates = 0;
+        while updates < budget {
+            let Some(pending) = heap.pop() else {
+                break;
+            };
+            if pending.priority != priorities[pending.index] {
+                continue;
+            }
+            if pending.priority <= self.config.tolerance {
+                break;
+            }
+            priorities[pending.index] = 0f64;
+            let changes = self.refresh_node(&nodes[pending.index])?;
+            message_updates += changes.len();
+            // A damped message only moves `1 - damping` of the way to its new value, so the node
+            // still owes the rest, `damping / (1 - damping)` times what it moved, and goes back in
+            // the queue for it.
+            let largest = changes.iter().map(|(_, change)| *change).fold(0f64, f64::max);
+            let owed = (pending.index, largest * self.config.damping / (1f64 - self.config.damping));
+            for (neighbor_index, change) in changes
+                .into_iter()
+                .map(|(neighbor, change)| (index[&neighbor], change))
+                .chain(std::iter::once(owed))
+            {
+                if change > priorities[neighbor_index] {
+                    priorities[neighbor_index] = change;
+                    heap.push(Pending {
+                        priority: change,
+                        index: neighbor_index,
+                    });
+                }
+            }
+            updates += 1;
+            if updates.is_multiple_of(nodes.len()) {
+                on_iteration(self)?;
+            }
+        }
+        if !updates.is_multiple_of(nodes.len().max(1)) {
+            on_iteration(self)?;
+        }
+        let residual = priorities.iter().cloned().fold(0f64, f64::max);
+        let converged = residual <= self.config.tolerance;
+        let iterations = updates.div_ceil(nodes.len().max(1));
+        trace!("propagate_by_residuals updates {} residual {}", updates, residual);
+        if !converged {
+            warn!(
+                "residual belief propagation did not converge: residual {} after {} node updates",
+                residual, updates
+            );
+        }
+        Ok(ConvergenceReport {
+            iterations,
+            residual,
+            converged,
+            schedule: MessageSchedule::Residual,
+            message_updates,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::WeightedWorld;
+    use crate::inference::inferencer::MarginalTable;
+
+    // Two stacked diamonds, a -> {b, c} -> {d, e} -> f, with links that alternately agree and
+    // disagree, and conflicting evidence on d and f. Beside them, a chain x0 -> ... -> x8 with
+    // evidence at its end, whose messages are settled after one pass.
+    fn propagate_frustrated_diamonds(args: &[&str]) -> (ConvergenceReport, MarginalTable) {
+        let mut all_args = vec!["--max_iterations", "200"];
+        all_args.extend_from_slice(args);
+        let mut world = WeightedWorld::new(&all_args);
+        let links = [("a", "b"), ("a", "c"), ("b", "d"), ("c", "d"), ("b", "e"), ("c", "e"), ("d", "f"), ("e", "f")];
+        for (index, (premise, conclusion)) in links.iter().enumerate() {
+            let weight = if index % 4 == 0 || index % 4 == 3 { -1f64 } else { 1f64 };
+            world.link(&[premise], conclusion, weight);
+        }
+        for i in 0..8 {
+            world.link(&[&format!("x{}", i)], &format!("x{}", i + 1), 0.5);
+        }
+        world.observe("d", 0f64);
+        world.observe("f", 1f64);
+        world.observe("x8", 1f64);
+        let mut inferencer = world.inferencer(&["f", "x8"]);
+        let report = inferencer.propagate_until_converged(None, &mut |_inferencer| Ok(())).unwrap();
+        (report, inferencer.build_marginal_table().unwrap())
+    }
+
+    #[test]
+    fn damping_settles_a_graph_that_oscillates_undamped() {
+        let (undamped, _) = propagate_frustrated_diamonds(&["--damping", "0"]);
+        assert!(!undamped.converged, "{:?}", undamped);
+        let (damped, _) = propagate_frustrated_diamonds(&["--damping", "0.2"]);
+        assert!(damped.converged, "{:?}", damped);
+        assert!(damped.iterations < 50);
+    }
+
+    #[test]
+    fn largest_residual_pops_first_and_ties_go_to_bfs_order() {
+        let mut heap = BinaryHeap::new();
+        for (index, priority) in [(0, 0.1), (1, f64::INFINITY), (2, 0.5), (3, 0.5)] {
+            heap.push(Pending { priority, index });
+        }
+        let order: Vec<usize> = std::iter::from_fn(|| heap.pop().map(|pending| pending.index)).collect();
+        assert_eq!(order, vec![1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn residual_schedule_matches_sweeps_with_fewer_updates() {
+        let (sweeps, sweep_marginals) = propagate_frustrated_diamonds(&["--damping", "0.2", "--tolerance", "1e-6"]);
+        let (residuals, residual_marginals) =
+            propagate_frustrated_diamonds(&["--damping", "0.2", "--tolerance", "1e-6", "--schedule", "residual"]);
+        assert!(sweeps.converged && residuals.converged, "{:?} {:?}", sweeps, residuals);
+        assert!(sweep_marginals.max_difference(&residual_marginals) < 1e-4);
+        assert!(residuals.message_updates < sweeps.message_updates, "{:?} {:?}", sweeps, residuals);
+    }
+}
@@ -0,0 +1,279 @@
+use super::{
+    inferencer::{compute_each_combination, likelihood_from_ratio, Inferencer, MarginalTable},
+    table::PropositionNode,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+// Variable elimination gives up if an intermediate factor would span more variables than this.
+pub const MAX_EXACT_FACTOR_VARIABLES: usize = 20;
+
+// A table over boolean variables: bit `i` of an index into `values` is the value of `variables[i]`.
+#[derive(Debug, Clone)]
+pub struct TableFactor {
+    pub variables: Vec<PropositionNode>,
+    pub values: Vec<f64>,
+}
+
+impl TableFactor {
+    pub fn new(variables: Vec<PropositionNode>, values: Vec<f64>) -> TableFactor {
+        assert_eq!(values.len(), 1 << variables.len());
+        TableFactor { variables, values }
+    }
+
+    pub fn contains(&self, variable: &PropositionNode) -> bool {
+        self.variables.contains(variable)
+    }
+
+    // For each of our variables, the bit it occupies in an index over `variables`.
+    fn bit_positions(&self, variables: &[PropositionNode]) -> Vec<usize> {
+        self.variables
+            .iter()
+            .map(|variable| variables.iter().position(|v| v == variable).unwrap())
+            .collect()
+    }
+
+    fn project_index(positions: &[usize], index: usize) -> usize {
+        let mut result = 0;
+        for (bit, position) in positions.iter().enumerate() {
+            if index & (1 << position) != 0 {
+                result |= 1 << bit;
+            }
+        }
+        result
+    }
+
+    pub fn product(&self, other: &TableFactor) -> TableFactor {
+        let mut variables = self.variables.clone();
+        for variable in &other.variables {
+            if !variables.contains(variable) {
+                variables.push(variable.clone());
+            }
+        }
+        let self_positions = self.bit_positions(&variables);
+        let other_positions = other.bit_positions(&variables);
+        let values = (0..(1usize << variables.len()))
+            .map(|index| {
+                self.values[Self::project_index(&self_positions, index)]
+                    * other.values[Self::project_index(&other_positions, index)]
+            })
+            .collect();
+        TableFactor { variables, values }
+    }
+
+    pub fn sum_out(&self, variable: &PropositionNode) -> TableFactor {
+        let position = match self.variables.iter().position(|v| v == variable) {
+            Some(position) => position,
+            None => return self.clone(),
+        };
+        let mut variables = self.variables.clone();
+        variables.remove(position);
+        let low_mask = (1usize << position) - 1;
+        let values = (0..(1usize << variables.len()))
+            .map(|index| {
+                let low = index & low_mask;
+                let high = (index & !low_mask) << 1;
+                self.values[high | low] + self.values[high | low | (1 << position)]
+            })
+            .collect();
+        TableFactor { variables, values }
+    }
+}
+
+// Greedy min-neighbors order over the interaction graph of `factors`.
+pub fn min_degree_elimination_order(factors: &[TableFactor]) -> Vec<PropositionNode> {
+    let mut neighbors: HashMap<PropositionNode, HashSet<PropositionNode>> = HashMap::new();
+    for factor in factors {
+        for variable in &factor.variables {
+            let entry = neighbors.entry(variable.clone()).or_default();
+            for other in &factor.variables {
+                if other != variable {
+                    entry.insert(other.clone());
+                }
+            }
+        }
+    }
+    let mut order = vec![];
+    while !neighbors.is_empty() {
+        let next = neighbors
+            .iter()
+            .min_by_key(|(variable, adjacent)| (adjacent.len(), variable.debug_string()))
+            .map(|(variable, _)| variable.clone())
+            .unwrap();
+        let adjacent = neighbors.remove(&next).unwrap();
+        for a in &adjacent {
+            let entry = neighbors.get_mut(a).unwrap();
+            entry.remove(&next);
+            for b in &adjacent {
+                if a != b {
+                    entry.insert(b.clone());
+                }
+            }
+        }
+        order.push(next);
+    }
+    order
+}
+
+// Sums every variable but `query` out of the product of `factors`, and returns P(query = true).
+pub fn eliminate_to_marginal(
+    factors: &[TableFactor],
+    order: &[PropositionNode],
+    query: &PropositionNode,
+) -> Result<f64, Box<dyn Error>> {
+    let mut pool: Vec<TableFactor> = factors.to_vec();
+    for variable in order {
+        if variable == query {
+            continue;
+        }
+        let (touching, rest): (Vec<TableFactor>, Vec<TableFactor>) =
+            pool.into_iter().partition(|factor| factor.contains(variable));
+        pool = rest;
+        if touching.is_empty() {
+            continue;
+        }
+        let mut product = touching[0].clone();
+        for factor in &touching[1..] {
+            product = product.product(factor);
+            if product.variables.len() > MAX_EXACT_FACTOR_VARIABLES {
+                return Err(format!(
+                    "Exact inference needs a factor over more than {} variables.",
+                    MAX_EXACT_FACTOR_VARIABLES
+                )
+                .into());
+            }
+        }
+        pool.push(product.sum_out(variable));
+    }
+    let mut result = TableFactor::new(vec![], vec![1f64]);
+    for factor in &pool {
+        result = result.product(factor);
+    }
+    if result.variables.len() != 1 {
+        return Err(format!("No factor mentions {:?}.", query).into());
+    }
+    let norm = result.values[0] + result.values[1];
+    if norm <= 0f64 {
+        return Err(format!("The evidence has zero probability under the model, querying {:?}.", query).into());
+    }
+    Ok(result.values[1] / norm)
+}
+
+impl Inferencer {
+    // One factor per node of the proposition graph: a prior for each root, a conditional table
+    // over the parents for every other node, and a likelihood `[1 - e, e]` for each observed
+    // node. For 0/1 evidence this is ordinary conditioning. Virtual evidence is a likelihood too.
+    pub fn build_exact_factors(&self) -> Result<Vec<TableFactor>, Box<dyn Error>> {
+        self.refresh_factor_cache()?;
+        let mut factors = vec![];
+        for node in &self.bfs_order {
+            // An intervention replaces the node's factor and any evidence with an indicator.
+            if let Some(value) = self.intervention_for(node) {
+                let values = if value { vec![0f64, 1f64] } else { vec![1f64, 0f64] };
+                factors.push(TableFactor::new(vec![node.clone()], values));
+                continue;
+            }
+            let parent_nodes = self.proposition_graph.get_all_backward(node);
+            if parent_nodes.is_empty() {
+                let prior = if node.is_single() {
+                    self.model.priors.prior_for_proposition(&node.extract_single())?
+                } else {
+                    1f64
+                };
+                factors.push(TableFactor::new(vec![node.clone()], vec![1f64 - prior, prior]));
+            } else {
+                if parent_nodes.len() >= MAX_EXACT_FACTOR_VARIABLES {
+                    return Err(format!(
+                        "Exact inference cannot tabulate {:?} with {} parents.",
+                        node,
+                        parent_nodes.len()
+                    )
+                    .into());
+                }
+                let mut variables = parent_nodes.clone();
+                variables.push(node.clone());
+                let mut values = vec![0f64; 1 << variables.len()];
+                let offset = 1 << parent_nodes.len();
+                // `compute_each_combination` sets parent `j` from bit `j`, matching our layout.
+                for (index, combination) in compute_each_combination(&parent_nodes).iter().enumerate() {
+                    let probability = self.score_factor_assignment(&parent_nodes, combination, node)?;
+                    values[index] = 1f64 - probability;
+                    values[index + offset] = probability;
+                }
+                factors.push(TableFactor::new(variables, values));
+            }
+            if self.is_observed(node)? {
+                let evidence = self
+                    .fact_memory
+                    .get_proposition_probability(&node.extract_single())?
+                    .unwrap();
+                factors.push(TableFactor::new(vec![node.clone()], vec![1f64 - evidence, evidence]));
+            } else if let Some(ratio) = self.virtual_evidence_for(node)? {
+                factors.push(TableFactor::new(vec![node.clone()], likelihood_from_ratio(ratio).to_vec()));
+            }
+        }
+        Ok(factors)
+    }
+
+    // Exact marginals by variable elimination, in the same layout as `build_marginal_table`, for
+    // checking loopy belief propagation on small graphs. Fails if the graph is too densely
+    // connected to eliminate within `MAX_EXACT_FACTOR_VARIABLES`.
+    pub fn compute_exact_marginals(&self) -> Result<MarginalTable, Box<dyn Error>> {
+        let factors = self.build_exact_factors()?;
+        let order = min_degree_elimination_order(&factors);
+        let mut entries = vec![];
+        for node in &self.bfs_order {
+            let probability = eliminate_to_marginal(&factors, &order, node)?;
+            entries.push((format!("{:?}", node), probability));
+        }
+        Ok(MarginalTable::new(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::{jack, WeightedWorld};
+
+    #[test]
+    fn and_of_two_roots_with_hard_and_virtual_evidence() {
+        // a and b have prior 1/2, and c follows {a, b} with P(c | a and b) = s, else 1 - s. With
+        // c observed and likelihoods l0, l1 on b, the weights of (a, b) are l0 (1 - s) for (0, 0)
+        // and (1, 0), l1 (1 - s) for (0, 1), and l1 s for (1, 1).
+        let mut world = WeightedWorld::new(&[]);
+        world.link(&["a", "b"], "c", 1f64);
+        world.observe("c", 1f64);
+        world.likelihood_ratio("b", 3f64);
+        let inferencer = world.inferencer(&["c"]);
+        let marginals = inferencer.compute_exact_marginals().unwrap();
+
+        let s = 1f64 / (1f64 + (-2f64).exp());
+        let (l0, l1) = (0.25, 0.75);
+        let total = 2f64 * l0 * (1f64 - s) + l1;
+        let group = inferencer.bfs_order.iter().find(|node| node.is_group()).unwrap();
+        for (node, expected) in [
+            (PropositionNode::from_single(&jack("a")), (l0 * (1f64 - s) + l1 * s) / total),
+            (PropositionNode::from_single(&jack("b")), l1 / total),
+            (group.clone(), l1 * s / total),
+            (PropositionNode::from_single(&jack("c")), 1f64),
+        ] {
+            let probability = marginals.get(&format!("{:?}", node)).unwrap();
+            assert!((probability - expected).abs() < 1e-12, "{:?} {} {}", node, probability, expected);
+        }
+    }
+
+    #[test]
+    fn summing_out_a_product_marginalizes() {
+        let x = PropositionNode::from_single(&jack("x"));
+        let y = PropositionNode::from_single(&jack("y"));
+        // P(x) = 0.2 and P(y | x) = 0.9, P(y | not x) = 0.4, so P(y) = 0.5.
+        let prior = TableFactor::new(vec![x.clone()], vec![0.8, 0.2]);
+        let conditional = TableFactor::new(vec![x.clone(), y.clone()], vec![0.6, 0.1, 0.4, 0.9]);
+        let marginal = prior.product(&conditional).sum_out(&x);
+        assert_eq!(marginal.variables, vec![y]);
+        assert!((marginal.values[1] - 0.5).abs() < 1e-12);
+        assert!((marginal.values[0] - 0.5).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,84 @@
+use super::{inferencer::Inferencer, table::PropositionNode};
+use std::{collections::HashMap, error::Error};
+
+// `FactorModel` predictions by conclusion and premise assignment, in the order of
+// `get_all_backward`, valid for one model version. Filled in as assignments are first scored.
+#[derive(Default)]
+pub struct FactorCache {
+    version: Option<u64>,
+    predictions: HashMap<(PropositionNode, Vec<bool>), f64>,
+}
+
+impl Inferencer {
+    // Clears the cached predictions if the model's parameters changed since they were computed.
+    // Each inference entry point calls this once, rather than every prediction asking the model.
+    pub fn refresh_factor_cache(&self) -> Result<(), Box<dyn Error>> {
+        let version = self.model.model.version()?;
+        let mut cache = self.factor_cache.borrow_mut();
+        if cache.version != version {
+            trace!("factor cache version {:?} -> {:?}, dropping {} predictions", cache.version, version, cache.predictions.len());
+            cache.predictions.clear();
+            cache.version = version;
+        }
+        Ok(())
+    }
+
+    // Looks `conclusion`'s prediction up under `premise_assignment`, or computes and keeps it.
+    pub fn cached_prediction(
+        &self,
+        premises: &[PropositionNode],
+        premise_assignment: &HashMap<PropositionNode, bool>,
+        conclusion: &PropositionNode,
+        predict: impl FnOnce() -> Result<f64, Box<dyn Error>>,
+    ) -> Result<f64, Box<dyn Error>> {
+        if self.factor_cache.borrow().version.is_none() {
+            return predict();
+        }
+        let assignment = premises.iter().map(|premise| premise_assignment[premise]).collect();
+        let key = (conclusion.clone(), assignment);
+        if let Some(probability) = self.factor_cache.borrow().predictions.get(&key) {
+            return Ok(*probability);
+        }
+        let probability = predict()?;
+        self.factor_cache.borrow_mut().predictions.insert(key, probability);
+        Ok(probability)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::fixtures::{jack, WeightedWorld};
+    use crate::inference::table::PropositionNode;
+    use crate::model::weights::{negative_feature, positive_feature, ExponentialWeights};
+    use std::collections::HashMap;
+
+    #[test]
+    fn saving_weights_drops_cached_predictions() {
+        let mut world = WeightedWorld::new(&[]);
+        world.link(&["a"], "b", 0.5);
+        world.observe("a", 1f64);
+        let mut inferencer = world.inferencer(&["b"]);
+        inferencer.do_full_forward_and_backward().unwrap();
+        let b = PropositionNode::from_single(&jack("b"));
+        let before = inferencer.get_marginal(&b).unwrap();
+
+        // Retrained weights for the same implication, saved while the inferencer is alive.
+        let key = inferencer.proposition_graph.inference_used.values().next().unwrap().parameter_key();
+        let mut weights = ExponentialWeights::new(world.resources.redis.get_connection().unwrap());
+        weights
+            .save_weights(&HashMap::from([
+                (positive_feature(&key, 1), 2f64),
+                (negative_feature(&key, 1), -2f64),
+                (positive_feature(&key, 0), -2f64),
+                (negative_feature(&key, 0), 2f64),
+            ]))
+            .unwrap();
+        inferencer.do_full_forward_and_backward().unwrap();
+        let after = inferencer.get_marginal(&b).unwrap();
+        assert!(after > before + 0.1, "{} {}", before, after);
+
+        let mut fresh = world.inferencer(&["b"]);
+        fresh.do_full_forward_and_backward().unwrap();
+        assert!((fresh.get_marginal(&b).unwrap() - after).abs() < 1e-9);
+    }
+}
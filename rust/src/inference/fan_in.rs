@@ -0,0 +1,215 @@
+use super::{
+    inferencer::{build_factor_context_for_assignment, groups_from_backlinks, Inferencer},
+    table::{log_diff_exp, log_normalize, log_sum_exp, PropositionNode},
+};
+use crate::{common::random::stream_rng, model::weights::CLASS_LABELS};
+use rand::{rngs::StdRng, Rng};
+use std::{collections::HashMap, error::Error};
+
+// For each index, the sum of `values` over every other index.
+fn sums_excluding_each(values: &[f64]) -> Vec<f64> {
+    let mut prefix = vec![0f64; values.len() + 1];
+    for (i, value) in values.iter().enumerate() {
+        prefix[i + 1] = prefix[i] + value;
+    }
+    let mut suffix = vec![0f64; values.len() + 1];
+    for (i, value) in values.iter().enumerate().rev() {
+        suffix[i] = suffix[i + 1] + value;
+    }
+    (0..values.len()).map(|i| prefix[i] + suffix[i + 1]).collect()
+}
+
+fn probability_true(log_message: &[f64; 2]) -> f64 {
+    (log_message[1] - log_sum_exp(log_message)).exp()
+}
+
+// A group node is true exactly when all of its terms are, so P(node = 1 | u) is the product of
+// the u_i, and its messages cost time linear in the terms.
+fn and_log_pi(log_messages: &[[f64; 2]]) -> [f64; 2] {
+    let log_all_true: f64 = log_messages.iter().map(|message| message[1]).sum();
+    let log_total: f64 = log_messages.iter().map(|message| log_sum_exp(message)).sum();
+    log_normalize([log_diff_exp(log_total, log_all_true), log_all_true])
+}
+
+fn and_log_lambda_messages(log_messages: &[[f64; 2]], log_lambda: [f64; 2]) -> Vec<[f64; 2]> {
+    let trues: Vec<f64> = log_messages.iter().map(|message| message[1]).collect();
+    let totals: Vec<f64> = log_messages.iter().map(|message| log_sum_exp(message)).collect();
+    let other_trues = sums_excluding_each(&trues);
+    let other_totals = sums_excluding_each(&totals);
+    (0..log_messages.len())
+        .map(|j| {
+            // With this term false the group is false; with it true, the group is true when all
+            // the other terms are.
+            let log_false = log_lambda[0] + other_totals[j];
+            let log_true = log_sum_exp(&[
+                log_lambda[1] + other_trues[j],
+                log_lambda[0] + log_diff_exp(other_totals[j], other_trues[j]),
+            ]);
+            log_normalize([log_false, log_true])
+        })
+        .collect()
+}
+
+impl Inferencer {
+    fn parent_log_messages(&self, parent_nodes: &[PropositionNode], node: &PropositionNode) -> Vec<[f64; 2]> {
+        parent_nodes
+            .iter()
+            .map(|parent| {
+                [
+                    self.data.get_log_pi_message(parent, node, 0).unwrap(),
+                    self.data.get_log_pi_message(parent, node, 1).unwrap(),
+                ]
+            })
+            .collect()
+    }
+
+    fn is_sampled_factor(&self, parent_nodes: &[PropositionNode]) -> bool {
+        parent_nodes.len() > self.config.max_exact_fan_in
+    }
+
+    // The same draws for `node` every round, so that its estimates only change when its incoming
+    // messages do, and sweeps can settle.
+    fn sampler_for(&self, node: &PropositionNode) -> StdRng {
+        stream_rng(self.config.seed, node.underlying_hash)
+    }
+
+    fn sample_premises(
+        &self,
+        rng: &mut StdRng,
+        parent_nodes: &[PropositionNode],
+        log_messages: &[[f64; 2]],
+    ) -> HashMap<PropositionNode, bool> {
+        parent_nodes
+            .iter()
+            .zip(log_messages)
+            .map(|(parent, message)| (parent.clone(), rng.gen::<f64>() < probability_true(message)))
+            .collect()
+    }
+
+    // The log pi value of `node` without enumerating every premise assignment, if its factor
+    // allows that: exactly for a group, or by sampling above `config.max_exact_fan_in`. The
+    // exponential model's factors have no such closed form, so conclusions are enumerated or
+    // sampled.
+    pub fn pi_compute_value_without_enumeration(
+        &self,
+        parent_nodes: &Vec<PropositionNode>,
+        node: &PropositionNode,
+    ) -> Result<Option<[f64; 2]>, Box<dyn Error>> {
+        let log_messages = self.parent_log_messages(parent_nodes, node);
+        if node.is_group() {
+            return Ok(Some(and_log_pi(&log_messages)));
+        }
+        if !self.is_sampled_factor(parent_nodes) {
+            return Ok(None);
+        }
+        let mut rng = self.sampler_for(node);
+        let mut total = 0f64;
+        for _ in 0..self.config.fan_in_samples {
+            let combination = self.sample_premises(&mut rng, parent_nodes, &log_messages);
+            total += self.score_factor_assignment(parent_nodes, &combination, node)?;
+        }
+        let mean = total / self.config.fan_in_samples as f64;
+        Ok(Some(log_normalize([(1f64 - mean).ln(), mean.ln()])))
+    }
+
+    // As `pi_compute_value_without_enumeration`, for the log lambda messages to each parent.
+    pub fn lambda_messages_without_enumeration(
+        &self,
+        parent_nodes: &Vec<PropositionNode>,
+        node: &PropositionNode,
+        log_lambda: [f64; 2],
+    ) -> Result<Option<Vec<[f64; 2]>>, Box<dyn Error>> {
+        let log_messages = self.parent_log_messages(parent_nodes, node);
+        if node.is_group() {
+            return Ok(Some(and_log_lambda_messages(&log_messages, log_lambda)));
+        }
+        if !self.is_sampled_factor(parent_nodes) {
+            return Ok(None);
+        }
+        let mut rng = self.sampler_for(node);
+        let lambda_true = probability_true(&log_lambda);
+        let mut result = vec![];
+        for to_parent in parent_nodes {
+            let mut totals = [0f64; 2];
+            for _ in 0..self.config.fan_in_samples {
+                let mut combination = self.sample_premises(&mut rng, parent_nodes, &log_messages);
+                for (parent_outcome, total) in totals.iter_mut().enumerate() {
+                    combination.insert(to_parent.clone(), parent_outcome == 1);
+                    let probability = self.score_factor_assignment(parent_nodes, &combination, node)?;
+                    *total += probability * lambda_true + (1f64 - probability) * (1f64 - lambda_true);
+                }
+            }
+            result.push(log_normalize([totals[0].ln(), totals[1].ln()]));
+        }
+        Ok(Some(result))
+    }
+
+    // Names every factor whose messages will be sampled rather than computed exactly.
+    pub fn warn_about_sampled_factors(&self) -> Result<(), Box<dyn Error>> {
+        for node in &self.bfs_order {
+            let parent_nodes = self.proposition_graph.get_all_backward(node);
+            if self.is_sampled_factor(&parent_nodes) && !node.is_group() {
+                warn!(
+                    "{:?} has {} premise groups, more than max_exact_fan_in {}; its messages are estimated from {} samples",
+                    node,
+                    parent_nodes.len(),
+                    self.config.max_exact_fan_in,
+                    self.config.fan_in_samples
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::{jack, WeightedWorld};
+
+    fn log_pair(probability: f64) -> [f64; 2] {
+        [(1f64 - probability).ln(), probability.ln()]
+    }
+
+    #[test]
+    fn and_pi_is_product_of_parents() {
+        let log_pi = and_log_pi(&[log_pair(0.5), log_pair(0.2)]);
+        assert!((log_pi[1].exp() - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn and_lambda_matches_enumeration() {
+        let (parents, lambda) = ([0.3, 0.6, 0.5], 0.8);
+        let log_messages: Vec<[f64; 2]> = parents.iter().map(|p| log_pair(*p)).collect();
+        let messages = and_log_lambda_messages(&log_messages, log_pair(lambda));
+        for (j, message) in messages.iter().enumerate() {
+            let mut expected = [0f64; 2];
+            for bits in 0..8usize {
+                let weight: f64 = (0..3)
+                    .filter(|i| *i != j)
+                    .map(|i| if bits & (1 << i) != 0 { parents[i] } else { 1f64 - parents[i] })
+                    .product();
+                let all_true = bits == 7;
+                expected[(bits >> j) & 1] += weight * if all_true { lambda } else { 1f64 - lambda };
+            }
+            let expected_true = expected[1] / (expected[0] + expected[1]);
+            assert!((message[1].exp() - expected_true).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn sweeps_converge_through_a_sampled_factor() {
+        let mut world = WeightedWorld::new(&["--max_exact_fan_in", "2", "--fan_in_samples", "64"]);
+        for (premise, weight) in [("a", 1f64), ("b", -0.5), ("c", 2f64)] {
+            world.link(&[premise], "d", weight);
+        }
+        world.observe("d", 1f64);
+        let mut inferencer = world.inferencer(&["d"]);
+        let report = inferencer.propagate_until_converged(None, &mut |_inferencer| Ok(())).unwrap();
+        assert!(report.converged, "{:?}", report);
+        let node = PropositionNode::from_single(&jack("c"));
+        let exact = inferencer.compute_exact_marginals().unwrap();
+        let estimate = inferencer.get_marginal(&node).unwrap();
+        assert!((estimate - exact.get(&format!("{:?}", node)).unwrap()).abs() < 0.1);
+    }
+}
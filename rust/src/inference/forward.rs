@@ -0,0 +1,415 @@
+use super::graph::{BackimplicationSource, PropositionFactor};
+use crate::{
+    common::{
+        interface::BeliefTable,
+        model::{FactorContext, InferenceModel},
+        proposition_db::RedisBeliefTable,
+        resources::FactoryResources,
+        setup::ConfigurationOptions,
+        train::TrainingPlan,
+    },
+    model::{
+        choose::ground_premise_for_conclusion,
+        objects::{
+            Argument, ConstantArgument, Domain, Predicate, PredicateFactor, Proposition, RoleMap,
+            EXISTENCE_FUNCTION,
+        },
+        ops::convert_to_proposition,
+    },
+};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    rc::Rc,
+};
+
+type Binding = HashMap<String, Argument>;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DerivedBelief {
+    pub proposition: Proposition,
+    pub probability: f64,
+    // The forward-chaining round that first grounded it; facts are round 0.
+    pub round: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ForwardReport {
+    // In the order derived.
+    pub derived: Vec<DerivedBelief>,
+    pub rounds: usize,
+    // False if `config.forward_rounds` rounds ran out while new conclusions were still appearing.
+    pub reached_fixpoint: bool,
+}
+
+// The conclusion roles fixed by matching `term` against the known `proposition`, or None if
+// they don't match. `role_map` maps conclusion roles to the term's roles.
+fn term_binding(term: &Predicate, role_map: &RoleMap, proposition: &Proposition) -> Option<Binding> {
+    if term.function != proposition.predicate.function {
+        return None;
+    }
+    let arguments: HashMap<String, Argument> = proposition
+        .predicate
+        .roles()
+        .into_iter()
+        .map(|labeled| (labeled.role_name, labeled.argument))
+        .collect();
+    let mut binding = Binding::new();
+    for labeled in term.roles() {
+        let argument = arguments.get(&labeled.role_name)?;
+        match (&labeled.argument, argument) {
+            (Argument::Constant(_), _) if &labeled.argument != argument => return None,
+            (Argument::Variable(variable), Argument::Constant(constant)) => {
+                if variable.domain != constant.domain {
+                    return None;
+                }
+                for (conclusion_role, premise_role) in &role_map.role_map {
+                    if premise_role == &labeled.role_name {
+                        binding.insert(conclusion_role.clone(), argument.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(binding)
+}
+
+fn merge_bindings(left: &Binding, right: &Binding) -> Option<Binding> {
+    let mut result = left.clone();
+    for (role, argument) in right {
+        match result.get(role) {
+            Some(existing) if existing != argument => return None,
+            Some(_) => {}
+            None => {
+                result.insert(role.clone(), argument.clone());
+            }
+        }
+    }
+    Some(result)
+}
+
+// Every grounding of `implication`'s conclusion whose premise terms are all known, matching the
+// non-existence terms against `known` and filling any conclusion role they leave open with each
+// entity of its domain. Implications whose premise is only existence are skipped: they hold for
+// every grounding, so they derive nothing from the facts.
+pub fn ground_forward(
+    implication: &PredicateFactor,
+    known: &HashMap<String, Vec<Proposition>>,
+    entities: &HashMap<Domain, Vec<Argument>>,
+) -> Result<Vec<Proposition>, Box<dyn Error>> {
+    let mut bindings = vec![Binding::new()];
+    let mut matched_any = false;
+    for (index, term) in implication.premise.terms.iter().enumerate() {
+        if term.function == EXISTENCE_FUNCTION {
+            continue;
+        }
+        matched_any = true;
+        let role_map = &implication.role_maps.role_maps[index];
+        let candidates = known.get(&term.function).map(Vec::as_slice).unwrap_or_default();
+        let mut next = vec![];
+        for binding in &bindings {
+            for proposition in candidates {
+                if let Some(merged) = term_binding(term, role_map, proposition)
+                    .and_then(|term_binding| merge_bindings(binding, &term_binding))
+                {
+                    next.push(merged);
+                }
+            }
+        }
+        bindings = next;
+    }
+    if !matched_any {
+        return Ok(vec![]);
+    }
+    for labeled in implication.conclusion.roles() {
+        if let Argument::Variable(variable) = &labeled.argument {
+            let choices = entities.get(&variable.domain).cloned().unwrap_or_default();
+            let mut next = vec![];
+            for binding in bindings {
+                if binding.contains_key(&labeled.role_name) {
+                    next.push(binding);
+                    continue;
+                }
+                for choice in &choices {
+                    let mut extended = binding.clone();
+                    extended.insert(labeled.role_name.clone(), choice.clone());
+                    next.push(extended);
+                }
+            }
+            bindings = next;
+        }
+    }
+    let known_set: HashSet<&Proposition> = known.values().flatten().collect();
+    let mut result = vec![];
+    let mut seen = HashSet::new();
+    for binding in &bindings {
+        let conclusion = convert_to_proposition(&implication.conclusion, binding)?;
+        let premise = ground_premise_for_conclusion(implication, &conclusion)?;
+        let supported = premise
+            .terms
+            .iter()
+            .all(|term| term.predicate.function == EXISTENCE_FUNCTION || known_set.contains(term));
+        if supported && seen.insert(conclusion.clone()) {
+            result.push(conclusion);
+        }
+    }
+    Ok(result)
+}
+
+// Bulk forward inference over the whole knowledge base: grounds every stored implication from
+// the facts outward until nothing new is derivable, then gives each derived proposition an
+// approximate probability in one sweep.
+pub struct ForwardChainer {
+    config: ConfigurationOptions,
+    model: Rc<InferenceModel>,
+}
+
+impl ForwardChainer {
+    pub fn new(config: &ConfigurationOptions, model: Rc<InferenceModel>) -> ForwardChainer {
+        ForwardChainer {
+            config: config.clone(),
+            model,
+        }
+    }
+
+    fn entities(&self) -> Result<HashMap<Domain, Vec<Argument>>, Box<dyn Error>> {
+        let mut result = HashMap::new();
+        for domain in self.model.graph.get_all_domains()? {
+            let arguments = self
+                .model
+                .graph
+                .get_entities_in_domain(&domain)?
+                .into_iter()
+                .map(|entity| Argument::Constant(ConstantArgument::new(domain, entity.name)))
+                .collect();
+            result.insert(domain, arguments);
+        }
+        Ok(result)
+    }
+
+    // Mean-field: each premise group is true with the product of its terms' probabilities, taken
+    // from the facts, from conclusions already scored in this sweep, or else from the priors.
+    fn score_conclusion(
+        &self,
+        conclusion: &Proposition,
+        beliefs: &HashMap<Proposition, f64>,
+    ) -> Result<f64, Box<dyn Error>> {
+        let factors: Vec<PropositionFactor> = self.model.graph.backimplications(conclusion)?;
+        if factors.is_empty() {
+            return self.model.priors.prior_for_proposition(conclusion);
+        }
+        let mut probabilities = vec![];
+        for factor in &factors {
+            let mut probability = 1f64;
+            for term in &factor.premise.terms {
+                probability *= match beliefs.get(term) {
+                    Some(belief) => *belief,
+                    None => self.model.priors.prior_for_proposition(term)?,
+                };
+            }
+            probabilities.push(probability);
+        }
+        let context = FactorContext {
+            factor: factors,
+            probabilities,
+        };
+        Ok(self.model.model.predict(&context)?.probability)
+    }
+
+    // `facts` maps observed propositions to their probability of being true. Each derived belief
+    // is also written to `derived_store` if given; facts are never derived or overwritten.
+    pub fn run(
+        &self,
+        facts: &HashMap<Proposition, f64>,
+        derived_store: Option<&dyn BeliefTable>,
+    ) -> Result<ForwardReport, Box<dyn Error>> {
+        let implications = self.model.graph.get_all_implications()?;
+        let entities = self.entities()?;
+        let mut known: HashMap<String, Vec<Proposition>> = HashMap::new();
+        let mut known_set: HashSet<Proposition> = HashSet::new();
+        for proposition in facts.keys() {
+            known.entry(proposition.predicate.function.clone()).or_default().push(proposition.clone());
+            known_set.insert(proposition.clone());
+        }
+        let mut derived_order: Vec<(Proposition, usize)> = vec![];
+        let mut rounds = 0;
+        let mut reached_fixpoint = false;
+        while rounds < self.config.forward_rounds {
+            rounds += 1;
+            let mut new_conclusions = vec![];
+            for implication in &implications {
+                for conclusion in ground_forward(implication, &known, &entities)? {
+                    if known_set.insert(conclusion.clone()) {
+                        new_conclusions.push(conclusion);
+                    }
+                }
+            }
+            if new_conclusions.is_empty() {
+                reached_fixpoint = true;
+                break;
+            }
+            trace!("forward chaining round {} derived {}", rounds, new_conclusions.len());
+            for conclusion in new_conclusions {
+                known.entry(conclusion.predicate.function.clone()).or_default().push(conclusion.clone());
+                derived_order.push((conclusion, rounds));
+            }
+        }
+        if !reached_fixpoint {
+            warn!(
+                "forward chaining stopped after {} rounds with conclusions still being derived",
+                rounds
+            );
+        }
+        let mut beliefs = facts.clone();
+        let mut derived = vec![];
+        for (proposition, round) in derived_order {
+            let probability = self.score_conclusion(&proposition, &beliefs)?;
+            beliefs.insert(proposition.clone(), probability);
+            if let Some(store) = derived_store {
+                store.store_proposition_probability(&proposition, probability)?;
+            }
+            derived.push(DerivedBelief {
+                proposition,
+                probability,
+                round,
+            });
+        }
+        Ok(ForwardReport {
+            derived,
+            rounds,
+            reached_fixpoint,
+        })
+    }
+}
+
+// Every training proposition with a stored probability. Test questions are left out, so that
+// forward chaining does not see their held-out labels.
+pub fn facts_from_belief_table(resources: &FactoryResources) -> Result<HashMap<Proposition, f64>, Box<dyn Error>> {
+    let plan = TrainingPlan::new(&resources.redis)?;
+    let proposition_db = RedisBeliefTable::new_shared(&resources.redis)?;
+    let mut facts = HashMap::new();
+    for proposition in plan.get_training_questions()? {
+        if let Some(probability) = proposition_db.get_proposition_probability(&proposition)? {
+            facts.insert(proposition, probability);
+        }
+    }
+    Ok(facts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::{jack, WeightedWorld};
+    use crate::model::{
+        creators::{conjunction, constant, implication, obj, predicate, proposition, sub, variable},
+        objects::PredicateGroup,
+    };
+
+    fn person(domain: Domain, name: &str) -> Argument {
+        constant(domain, name.to_string())
+    }
+
+    #[test]
+    fn grounds_joined_premises_and_open_roles() {
+        // lonely(x) -> like(x, y), for every jill y.
+        let lonely = implication(
+            PredicateGroup::new(vec![predicate("lonely".to_string(), vec![sub(variable(Domain::Jack))])]),
+            predicate("like".to_string(), vec![sub(variable(Domain::Jack)), obj(variable(Domain::Jill))]),
+            vec![RoleMap::new(HashMap::from([("sub".to_string(), "sub".to_string())]))],
+        );
+        // like(jill, jack) & like(jack, jill) -> date(jack, jill).
+        let mutual = implication(
+            conjunction(vec![
+                predicate("like".to_string(), vec![sub(variable(Domain::Jill)), obj(variable(Domain::Jack))]),
+                predicate("like".to_string(), vec![sub(variable(Domain::Jack)), obj(variable(Domain::Jill))]),
+            ]),
+            predicate("date".to_string(), vec![sub(variable(Domain::Jack)), obj(variable(Domain::Jill))]),
+            vec![
+                RoleMap::new(HashMap::from([
+                    ("sub".to_string(), "obj".to_string()),
+                    ("obj".to_string(), "sub".to_string()),
+                ])),
+                RoleMap::new(HashMap::from([
+                    ("sub".to_string(), "sub".to_string()),
+                    ("obj".to_string(), "obj".to_string()),
+                ])),
+            ],
+        );
+        let (jack, jill, jane) = (
+            person(Domain::Jack, "jack"),
+            person(Domain::Jill, "jill"),
+            person(Domain::Jill, "jane"),
+        );
+        let entities = HashMap::from([(Domain::Jack, vec![jack.clone()]), (Domain::Jill, vec![jill.clone(), jane.clone()])]);
+        let mut known: HashMap<String, Vec<Proposition>> = HashMap::new();
+        known
+            .entry("lonely".to_string())
+            .or_default()
+            .push(proposition("lonely".to_string(), vec![sub(jack.clone())]));
+        let likes = ground_forward(&lonely, &known, &entities).unwrap();
+        assert_eq!(likes.len(), 2);
+        assert!(ground_forward(&mutual, &known, &entities).unwrap().is_empty());
+        known.insert("like".to_string(), likes);
+        known
+            .get_mut("like")
+            .unwrap()
+            .push(proposition("like".to_string(), vec![sub(jill.clone()), obj(jack.clone())]));
+        let dates = ground_forward(&mutual, &known, &entities).unwrap();
+        assert_eq!(dates, vec![proposition("date".to_string(), vec![sub(jack), obj(jill)])]);
+    }
+
+    #[test]
+    fn chains_from_training_facts_only() {
+        let mut world = WeightedWorld::new(&[]);
+        world.link(&["a"], "b", 2f64);
+        world.link(&["b"], "c", 1f64);
+        let mut plan = TrainingPlan::new(&world.resources.redis).unwrap();
+        plan.maybe_add_to_training(true, &jack("a")).unwrap();
+        world.observe("a", 1f64);
+        // A held-out label, which must not become a fact.
+        plan.maybe_add_to_test(true, &jack("c")).unwrap();
+        world.observe("c", 0f64);
+        let facts = facts_from_belief_table(&world.resources).unwrap();
+        assert_eq!(facts, HashMap::from([(jack("a"), 1f64)]));
+
+        let derived_store = RedisBeliefTable::new_derived_shared(&world.resources.redis).unwrap();
+        derived_store.store_proposition_probability(&jack("stale"), 1f64).unwrap();
+        RedisBeliefTable::clear_derived(&world.resources.redis).unwrap();
+        let model = InferenceModel::new_shared(&world.resources).unwrap();
+        let report = ForwardChainer::new(&world.config, model)
+            .run(&facts, Some(derived_store.as_ref()))
+            .unwrap();
+        assert!(report.reached_fixpoint);
+        let derived: Vec<(Proposition, usize)> = report
+            .derived
+            .iter()
+            .map(|belief| (belief.proposition.clone(), belief.round))
+            .collect();
+        assert_eq!(derived, vec![(jack("b"), 1), (jack("c"), 2)]);
+        // With the weights of `WeightedWorld::link`, P(conclusion) = sigmoid(2 w (2 p - 1)).
+        let b = 1f64 / (1f64 + (-4f64).exp());
+        let c = 1f64 / (1f64 + (-2f64 * (2f64 * b - 1f64)).exp());
+        assert!((report.derived[0].probability - b).abs() < 1e-12);
+        assert!((report.derived[1].probability - c).abs() < 1e-12);
+        assert_eq!(derived_store.get_proposition_probability(&jack("c")).unwrap(), Some(report.derived[1].probability));
+        assert_eq!(derived_store.get_proposition_probability(&jack("stale")).unwrap(), None);
+    }
+
+    #[test]
+    fn rounds_are_capped_by_forward_rounds_over_the_stored_domains() {
+        let mut world = WeightedWorld::new(&["--forward_rounds", "1"]);
+        world.link(&["a"], "b", 2f64);
+        world.link(&["b"], "c", 1f64);
+        let model = InferenceModel::new_shared(&world.resources).unwrap();
+        // `WeightedWorld` stores only jack, so no jills are grounded.
+        assert_eq!(model.graph.get_all_domains().unwrap(), vec![Domain::Jack]);
+        let facts = HashMap::from([(jack("a"), 1f64)]);
+        let report = ForwardChainer::new(&world.config, model).run(&facts, None).unwrap();
+        assert_eq!(report.rounds, 1);
+        assert!(!report.reached_fixpoint);
+        let derived: Vec<Proposition> = report.derived.iter().map(|belief| belief.proposition.clone()).collect();
+        assert_eq!(derived, vec![jack("b")]);
+    }
+}
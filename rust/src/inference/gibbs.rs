@@ -0,0 +1,251 @@
+use super::{
+    inferencer::{Inferencer, MarginalTable},
+    sampling::SamplingNetwork,
+};
+use crate::common::random::random_f64;
+use serde::Serialize;
+use std::{collections::HashMap, error::Error};
+
+// Each chain's kept samples are cut into this many batches to estimate the Monte-Carlo error.
+const BATCHES_PER_CHAIN: usize = 10;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SampleDiagnostics {
+    pub node: String,
+    // Batch-means estimate of the Monte-Carlo standard error of the marginal.
+    pub standard_error: f64,
+    // Gelman-Rubin potential scale reduction; `None` with a single chain.
+    pub r_hat: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SamplingReport {
+    pub marginals: MarginalTable,
+    pub diagnostics: Vec<SampleDiagnostics>,
+}
+
+impl<'a> SamplingNetwork<'a> {
+    // Draws a starting state forward from the priors, with observed nodes drawn from their evidence.
+    fn initial_state(&mut self) -> Result<Vec<bool>, Box<dyn Error>> {
+        let mut state = vec![false; self.nodes.len()];
+        for node in 0..self.nodes.len() {
+            state[node] = if self.is_group[node] {
+                self.group_value(node, &state)
+            } else {
+                let probability = match self.evidence[node] {
+                    Some(probability) => probability,
+                    None => self.probability_true(node, &state)?,
+                };
+                random_f64() < probability
+            };
+        }
+        for node in 0..self.nodes.len() {
+            if self.is_group[node] {
+                state[node] = self.group_value(node, &state);
+            }
+        }
+        Ok(state)
+    }
+
+    fn refresh_groups(&self, node: usize, state: &mut [bool]) {
+        for child in &self.children[node] {
+            if self.is_group[*child] {
+                state[*child] = self.group_value(*child, state);
+            }
+        }
+    }
+
+    // The unnormalized weight of `state` restricted to the factors that mention `node`.
+    fn markov_blanket_weight(&mut self, node: usize, state: &[bool]) -> Result<f64, Box<dyn Error>> {
+        let mut weight = self.conditional_weight(node, state)? * self.evidence_weight(node, state[node]);
+        // A conclusion reached through several of our groups still has just one factor.
+        let mut affected = vec![];
+        for child in &self.children[node] {
+            let conclusions = if self.is_group[*child] {
+                self.children[*child].clone()
+            } else {
+                vec![*child]
+            };
+            for conclusion in conclusions {
+                if !affected.contains(&conclusion) {
+                    affected.push(conclusion);
+                }
+            }
+        }
+        for conclusion in affected {
+            weight *= self.conditional_weight(conclusion, state)?;
+        }
+        Ok(weight)
+    }
+
+    fn resample(&mut self, node: usize, state: &mut [bool]) -> Result<(), Box<dyn Error>> {
+        let mut weights = [0f64; 2];
+        for (value, weight) in weights.iter_mut().enumerate() {
+            state[node] = value == 1;
+            self.refresh_groups(node, state);
+            *weight = self.markov_blanket_weight(node, state)?;
+        }
+        let norm = weights[0] + weights[1];
+        if norm <= 0f64 {
+            return Err(format!("Gibbs sampling reached a state of zero probability at {:?}.", self.nodes[node]).into());
+        }
+        state[node] = random_f64() < weights[1] / norm;
+        self.refresh_groups(node, state);
+        Ok(())
+    }
+
+    fn sweep(&mut self, state: &mut [bool]) -> Result<(), Box<dyn Error>> {
+        for node in 0..self.nodes.len() {
+            if !self.is_group[node] && self.interventions[node].is_none() {
+                self.resample(node, state)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn run_chain(&mut self, burn_in: usize, samples: usize, thinning: usize) -> Result<Vec<Vec<bool>>, Box<dyn Error>> {
+        let mut state = self.initial_state()?;
+        for _ in 0..burn_in {
+            self.sweep(&mut state)?;
+        }
+        let mut result = vec![];
+        for _ in 0..samples {
+            for _ in 0..thinning {
+                self.sweep(&mut state)?;
+            }
+            result.push(state.clone());
+        }
+        Ok(result)
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn sample_variance(values: &[f64]) -> f64 {
+    let center = mean(values);
+    values.iter().map(|value| (value - center).powi(2)).sum::<f64>() / (values.len() as f64 - 1f64)
+}
+
+fn gelman_rubin(chain_means: &[f64], samples_per_chain: usize) -> Option<f64> {
+    if chain_means.len() < 2 || samples_per_chain < 2 {
+        return None;
+    }
+    let n = samples_per_chain as f64;
+    // The sample variance of a 0/1 chain with mean `m` is m(1 - m) n / (n - 1).
+    let within = mean(
+        &chain_means
+            .iter()
+            .map(|m| m * (1f64 - m) * n / (n - 1f64))
+            .collect::<Vec<f64>>(),
+    );
+    let between = n * sample_variance(chain_means);
+    if within == 0f64 {
+        return Some(if between == 0f64 { 1f64 } else { f64::INFINITY });
+    }
+    let pooled = (n - 1f64) / n * within + between / n;
+    Some((pooled / within).sqrt())
+}
+
+fn batch_means_standard_error(chains: &[Vec<f64>], probability: f64) -> f64 {
+    let samples_per_chain = chains[0].len();
+    let batch_size = (samples_per_chain / BATCHES_PER_CHAIN).max(1);
+    let mut batch_means = vec![];
+    for chain in chains {
+        for batch in chain.chunks_exact(batch_size) {
+            batch_means.push(mean(batch));
+        }
+    }
+    if batch_means.len() < 2 {
+        let total = (chains.len() * samples_per_chain) as f64;
+        return (probability * (1f64 - probability) / total).sqrt();
+    }
+    (sample_variance(&batch_means) / batch_means.len() as f64).sqrt()
+}
+
+impl Inferencer {
+    // Marginals of every node by Gibbs sampling over `config.chains` chains, each discarding
+    // `config.burn_in` sweeps and then keeping every `config.thinning`-th of `config.gibbs_samples`
+    // samples. Evidence enters as a likelihood `[1 - e, e]`, as in `compute_exact_marginals`.
+    pub fn gibbs_sample(&self) -> Result<SamplingReport, Box<dyn Error>> {
+        let mut network = SamplingNetwork::new(self)?;
+        let mut chains = vec![];
+        for chain in 0..self.config.chains {
+            trace!("gibbs_sample chain {}", chain);
+            chains.push(network.run_chain(self.config.burn_in, self.config.gibbs_samples, self.config.thinning)?);
+        }
+        let mut entries = vec![];
+        let mut diagnostics = vec![];
+        for (index, node) in network.nodes.iter().enumerate() {
+            let traces: Vec<Vec<f64>> = chains
+                .iter()
+                .map(|samples| {
+                    samples
+                        .iter()
+                        .map(|state| if state[index] { 1f64 } else { 0f64 })
+                        .collect()
+                })
+                .collect();
+            let chain_means: Vec<f64> = traces.iter().map(|trace| mean(trace)).collect();
+            let probability = mean(&chain_means);
+            let node_string = format!("{:?}", node);
+            diagnostics.push(SampleDiagnostics {
+                node: node_string.clone(),
+                standard_error: batch_means_standard_error(&traces, probability),
+                r_hat: gelman_rubin(&chain_means, self.config.gibbs_samples),
+            });
+            entries.push((node_string, probability));
+        }
+        Ok(SamplingReport {
+            marginals: MarginalTable::new(entries),
+            diagnostics,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::WeightedWorld;
+
+    #[test]
+    fn agreeing_chains_have_r_hat_near_one() {
+        let r_hat = gelman_rubin(&[0.5, 0.5, 0.5, 0.5], 1000).unwrap();
+        assert!((r_hat - 1f64).abs() < 1e-3);
+    }
+
+    #[test]
+    fn disagreeing_chains_have_large_r_hat() {
+        let r_hat = gelman_rubin(&[0.1, 0.9, 0.1, 0.9], 1000).unwrap();
+        assert!(r_hat > 1.5);
+        assert!(gelman_rubin(&[0.5], 1000).is_none());
+    }
+
+    #[test]
+    fn chains_with_evidence_match_exact_within_standard_errors() {
+        let mut world = WeightedWorld::new(&[
+            "--seed", "5", "--chains", "4", "--gibbs_samples", "4000", "--burn_in", "200", "--thinning", "1",
+        ]);
+        for (premise, conclusion, weight) in [("a", "b", 1f64), ("a", "c", -0.5), ("b", "d", 0.8), ("c", "d", 0.8)] {
+            world.link(&[premise], conclusion, weight);
+        }
+        world.observe("d", 1f64);
+        world.likelihood_ratio("b", 0.5);
+        let inferencer = world.inferencer(&["d"]);
+        let exact = inferencer.compute_exact_marginals().unwrap();
+        let report = inferencer.gibbs_sample().unwrap();
+        for diagnostic in &report.diagnostics {
+            let estimate = report.marginals.get(&diagnostic.node).unwrap();
+            let probability = exact.get(&diagnostic.node).unwrap();
+            assert!(
+                (estimate - probability).abs() < 4f64 * diagnostic.standard_error + 1e-9,
+                "{:?} {} {}",
+                diagnostic,
+                estimate,
+                probability
+            );
+            assert!(diagnostic.r_hat.unwrap() < 1.05, "{:?}", diagnostic);
+        }
+    }
+}
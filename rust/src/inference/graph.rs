@@ -2,13 +2,14 @@ use std::{
     collections::{HashMap, HashSet, VecDeque},
     error::Error,
     rc::Rc,
+    time::{Duration, Instant},
 };
 
 use env_logger::init;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    common::{graph::InferenceGraph, redis::RedisManager},
+    common::{graph::InferenceGraph, redis::RedisManager, setup::ConfigurationOptions},
     model::{
         choose::{compute_search_predicates, extract_backimplications_from_proposition},
         objects::{GroupRoleMap, PredicateFactor, Proposition, PropositionGroup},
@@ -34,21 +35,160 @@ impl PropositionFactor {
     }
 }
 
+// Where graph construction finds the implications that conclude a proposition.
+pub trait BackimplicationSource {
+    fn backimplications(&self, conclusion: &Proposition) -> Result<Vec<PropositionFactor>, Box<dyn Error>>;
+}
+
+impl BackimplicationSource for InferenceGraph {
+    fn backimplications(&self, conclusion: &Proposition) -> Result<Vec<PropositionFactor>, Box<dyn Error>> {
+        extract_backimplications_from_proposition(self, conclusion)
+    }
+}
+
+// A fixed table of backimplications per conclusion, e.g. for building graphs without Redis.
+impl BackimplicationSource for HashMap<Proposition, Vec<PropositionFactor>> {
+    fn backimplications(&self, conclusion: &Proposition) -> Result<Vec<PropositionFactor>, Box<dyn Error>> {
+        Ok(self.get(conclusion).cloned().unwrap_or_default())
+    }
+}
+
+// What to do when expanding a premise would lead back to a proposition still being expanded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CyclePolicy {
+    Error,
+    // Leave out each implication that closes a cycle, with a warning.
+    Break,
+}
+
+// Budgets bound the backward expansion: a proposition that would exceed one is left
+// unexpanded, on the frontier, and inference treats it as a root with its prior.
+#[derive(Debug, Clone)]
+pub struct GraphBuildOptions {
+    pub cycle_policy: CyclePolicy,
+    // Targets are at depth 0, and their premises at depth 1.
+    pub max_depth: Option<usize>,
+    // Expansion stops once the graph has this many nodes, counting premise groups.
+    pub max_nodes: Option<usize>,
+    pub time_budget: Option<Duration>,
+}
+
+impl Default for GraphBuildOptions {
+    fn default() -> Self {
+        GraphBuildOptions {
+            cycle_policy: CyclePolicy::Error,
+            max_depth: None,
+            max_nodes: None,
+            time_budget: None,
+        }
+    }
+}
+
+impl GraphBuildOptions {
+    pub fn from_config(config: &ConfigurationOptions) -> GraphBuildOptions {
+        GraphBuildOptions {
+            cycle_policy: if config.break_cycles {
+                CyclePolicy::Break
+            } else {
+                CyclePolicy::Error
+            },
+            max_depth: config.max_graph_depth,
+            max_nodes: config.max_graph_nodes,
+            time_budget: config.graph_time_budget_ms.map(Duration::from_millis),
+        }
+    }
+}
+
+// What a budget kept out of the graph.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TruncationReport {
+    // Unexpanded propositions, which inference treats as roots with their priors.
+    pub frontier: Vec<Proposition>,
+    pub hit_depth_limit: bool,
+    pub hit_node_limit: bool,
+    pub hit_time_limit: bool,
+}
+
+impl TruncationReport {
+    pub fn is_truncated(&self) -> bool {
+        !self.frontier.is_empty()
+    }
+}
+
 pub struct PropositionGraph {
-    pub predicate_graph: Rc<InferenceGraph>,
     pub single_forward: HashMap<Proposition, HashSet<PropositionGroup>>,
     pub single_backward: HashMap<Proposition, HashSet<PropositionGroup>>,
     pub group_forward: HashMap<PropositionGroup, HashSet<Proposition>>,
     pub inference_used: HashMap<(PropositionGroup, Proposition), PredicateFactor>,
     pub roots: HashSet<Proposition>,
     pub all_nodes: HashSet<PropositionNode>,
-    pub target: Proposition,
+    pub targets: Vec<Proposition>,
+    pub truncation: TruncationReport,
+}
+
+// Propositions already expanded with the shallowest depth they were expanded at, the chain of
+// conclusions currently being expanded, and the frontier left by the budgets. Under a depth
+// limit, a proposition reached again at a shallower depth is expanded again, so its premises get
+// the depth budget of its shallowest path rather than of the first path the search took.
+struct VisitState<'a> {
+    source: &'a dyn BackimplicationSource,
+    options: &'a GraphBuildOptions,
+    expanded: HashMap<Proposition, usize>,
+    stack: Vec<Proposition>,
+    frontier: HashSet<Proposition>,
+    started: Instant,
+}
+
+impl<'a> VisitState<'a> {
+    // Records which budget, if any, stops `single` at `depth` from being expanded.
+    fn over_budget(&self, graph: &mut PropositionGraph, depth: usize) -> bool {
+        let truncation = &mut graph.truncation;
+        if self.options.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            truncation.hit_depth_limit = true;
+            return true;
+        }
+        if self.options.max_nodes.is_some_and(|max_nodes| graph.all_nodes.len() >= max_nodes) {
+            truncation.hit_node_limit = true;
+            return true;
+        }
+        if self.options.time_budget.is_some_and(|budget| self.started.elapsed() >= budget) {
+            truncation.hit_time_limit = true;
+            return true;
+        }
+        false
+    }
+}
+
+fn describe_cycle(stack: &[Proposition], term: &Proposition) -> String {
+    let start = stack.iter().position(|proposition| proposition == term).unwrap();
+    let mut path: Vec<String> = stack[start..].iter().map(|p| p.debug_string()).collect();
+    path.push(term.debug_string());
+    path.join(" <- ")
 }
 
 fn initialize_visit_single(
     graph: &mut PropositionGraph,
+    visit: &mut VisitState,
     single: &Proposition,
+    depth: usize,
 ) -> Result<(), Box<dyn Error>> {
+    if let Some(&expanded_depth) = visit.expanded.get(single) {
+        if visit.options.max_depth.is_none() || expanded_depth <= depth {
+            trace!("Already expanded {:?}", single.hash_string());
+            return Ok(());
+        }
+        trace!("Expanding {:?} again at the shallower depth {}", single.hash_string(), depth);
+    }
+    if visit.over_budget(graph, depth) {
+        trace!("Leaving {:?} on the frontier at depth {}", single.hash_string(), depth);
+        graph.all_nodes.insert(PropositionNode::from_single(single));
+        graph.roots.insert(single.clone());
+        visit.frontier.insert(single.clone());
+        return Ok(());
+    }
+    if visit.frontier.remove(single) {
+        graph.roots.remove(single);
+    }
     // Green for starting a new operation
     trace!(
         "\x1b[32mInitializing visit for proposition: {:?}\x1b[0m",
@@ -57,68 +197,88 @@ fn initialize_visit_single(
     graph
         .all_nodes
         .insert(PropositionNode::from_single(single));
-    let inference_factors =
-        extract_backimplications_from_proposition(&graph.predicate_graph, single)?;
+    visit.stack.push(single.clone());
+    let inference_factors = visit.source.backimplications(single)?;
     // Yellow for showing counts or lengths
     trace!(
         "\x1b[33mInference factors count: {}\x1b[0m",
         inference_factors.len()
     );
 
-    if inference_factors.is_empty() {
-        // Blue for specific condition-related messages
-        trace!("\x1b[34mNo inference factors. Adding to roots.\x1b[0m");
-        graph.roots.insert(single.clone());
-    } else {
-        for inference_factor in &inference_factors {
-            // Cyan for loop iteration
-            trace!(
-                "\x1b[36mProcessing inference factor: {:?}\x1b[0m",
-                inference_factor.debug_string()
-            );
+    let mut factors_added = 0;
+    for inference_factor in &inference_factors {
+        // Cyan for loop iteration
+        trace!(
+            "\x1b[36mProcessing inference factor: {:?}\x1b[0m",
+            inference_factor.debug_string()
+        );
+        if let Some(term) = inference_factor
+            .premise
+            .terms
+            .iter()
+            .find(|term| visit.stack.contains(term))
+        {
+            let cycle = describe_cycle(&visit.stack, term);
+            match visit.options.cycle_policy {
+                CyclePolicy::Error => {
+                    return Err(format!("Cycle in the rule set: {}", cycle).into());
+                }
+                CyclePolicy::Break => {
+                    warn!("Leaving out {} to break the cycle {}", inference_factor.debug_string(), cycle);
+                    continue;
+                }
+            }
+        }
+        factors_added += 1;
 
-            let inference_used_key = (inference_factor.premise.clone(), inference_factor.conclusion.clone());
-            graph.inference_used.insert(inference_used_key, inference_factor.inference.clone());
+        let inference_used_key = (inference_factor.premise.clone(), inference_factor.conclusion.clone());
+        graph.inference_used.insert(inference_used_key, inference_factor.inference.clone());
 
-            trace!(
-                "\x1b[36mUpdating single_backward for conclusion: {:?}\x1b[0m",
-                inference_factor.conclusion.hash_string()
-            );
+        trace!(
+            "\x1b[36mUpdating single_backward for conclusion: {:?}\x1b[0m",
+            inference_factor.conclusion.hash_string()
+        );
+        graph
+            .single_backward
+            .entry(inference_factor.conclusion.clone())
+            .or_default()
+            .insert(inference_factor.premise.clone());
+
+        trace!(
+            "\x1b[36mUpdating group_forward for premise: {:?}\x1b[0m",
+            inference_factor.premise.hash_string()
+        );
+        graph
+            .group_forward
+            .entry(inference_factor.premise.clone())
+            .or_default()
+            .insert(inference_factor.conclusion.clone());
+
+        graph
+            .all_nodes
+            .insert(PropositionNode::from_group(&inference_factor.premise));
+
+        for term in &inference_factor.premise.terms {
+            trace!("\x1b[35mProcessing term: {:?}\x1b[0m", term.hash_string());
             graph
-                .single_backward
-                .entry(inference_factor.conclusion.clone())
-                .or_insert_with(HashSet::new)
+                .single_forward
+                .entry(term.clone())
+                .or_default()
                 .insert(inference_factor.premise.clone());
-
             trace!(
-                "\x1b[36mUpdating group_forward for premise: {:?}\x1b[0m",
-                inference_factor.premise.hash_string()
+                "\x1b[35mRecursively initializing visit for term: {:?}\x1b[0m",
+                term.hash_string()
             );
-            graph
-                .group_forward
-                .entry(inference_factor.premise.clone())
-                .or_insert_with(HashSet::new)
-                .insert(inference_factor.conclusion.clone());
-
-            graph
-                .all_nodes
-                .insert(PropositionNode::from_group(&inference_factor.premise));
-
-            for term in &inference_factor.premise.terms {
-                trace!("\x1b[35mProcessing term: {:?}\x1b[0m", term.hash_string());
-                graph
-                    .single_forward
-                    .entry(term.clone())
-                    .or_insert_with(HashSet::new)
-                    .insert(inference_factor.premise.clone());
-                trace!(
-                    "\x1b[35mRecursively initializing visit for term: {:?}\x1b[0m",
-                    term.hash_string()
-                );
-                initialize_visit_single(graph, term)?;
-            }
+            initialize_visit_single(graph, visit, term, depth + 1)?;
         }
     }
+    if factors_added == 0 {
+        // Blue for specific condition-related messages
+        trace!("\x1b[34mNo inference factors. Adding to roots.\x1b[0m");
+        graph.roots.insert(single.clone());
+    }
+    visit.stack.pop();
+    visit.expanded.insert(single.clone(), depth);
 
     // Green for completion messages
     trace!(
@@ -133,18 +293,63 @@ impl PropositionGraph {
         predicate_graph: Rc<InferenceGraph>,
         target: &Proposition,
     ) -> Result<Rc<PropositionGraph>, Box<dyn Error>> {
-        let mut graph = PropositionGraph {
+        Self::new_shared_for_targets(
             predicate_graph,
+            std::slice::from_ref(target),
+            &GraphBuildOptions::default(),
+        )
+    }
+
+    pub fn new_shared_for_targets(
+        predicate_graph: Rc<InferenceGraph>,
+        targets: &[Proposition],
+        options: &GraphBuildOptions,
+    ) -> Result<Rc<PropositionGraph>, Box<dyn Error>> {
+        Ok(Rc::new(Self::build(predicate_graph.as_ref(), targets, options)?))
+    }
+
+    // One graph grown backward from every target. Each proposition is expanded once (again only
+    // when a depth limit meets it on a shallower path), so ancestors that targets or premises
+    // share are one node, and a single propagation gives the marginals of all targets.
+    pub fn build(
+        source: &dyn BackimplicationSource,
+        targets: &[Proposition],
+        options: &GraphBuildOptions,
+    ) -> Result<PropositionGraph, Box<dyn Error>> {
+        let mut graph = PropositionGraph {
             single_forward: HashMap::new(),
             single_backward: HashMap::new(),
             group_forward: HashMap::new(),
             inference_used: HashMap::new(),
             roots: HashSet::new(),
             all_nodes: HashSet::new(),
-            target: target.clone(),
+            targets: targets.to_vec(),
+            truncation: TruncationReport::default(),
+        };
+        let mut visit = VisitState {
+            source,
+            options,
+            expanded: HashMap::new(),
+            stack: vec![],
+            frontier: HashSet::new(),
+            started: Instant::now(),
         };
-        initialize_visit_single(&mut graph, target)?;
-        Ok(Rc::new(graph))
+        for target in targets {
+            initialize_visit_single(&mut graph, &mut visit, target, 0)?;
+        }
+        let mut frontier: Vec<Proposition> = visit.frontier.into_iter().collect();
+        frontier.sort_by_key(|proposition| proposition.debug_string());
+        graph.truncation.frontier = frontier;
+        if graph.truncation.is_truncated() {
+            warn!(
+                "PropositionGraph truncated: {} frontier propositions treated as roots, depth limit {}, node limit {}, time limit {}",
+                graph.truncation.frontier.len(),
+                graph.truncation.hit_depth_limit,
+                graph.truncation.hit_node_limit,
+                graph.truncation.hit_time_limit
+            );
+        }
+        Ok(graph)
     }
 
     pub fn get_inference_used(&self, premise:&PropositionGroup, conclusion: &Proposition) -> PredicateFactor {
@@ -234,7 +439,7 @@ impl PropositionGraph {
     }
 
     pub fn get_bfs_order(&self) -> Vec<PropositionNode> {
-        create_bfs_order(&self)
+        create_bfs_order(self)
     }
 }
 
@@ -265,32 +470,177 @@ impl PropositionGraph {
     }
 }
 
-fn reverse_prune_duplicates(raw_order: &Vec<(i32, PropositionNode)>) -> Vec<PropositionNode> {
-    let mut seen = HashSet::new();
-    let mut result = vec![];
-    for (depth, node) in raw_order.iter().rev() {
-        if !seen.contains(node) {
-            result.push(node.clone());
+// Every node after all of its parents, breadth first from the roots. A node is queued when its
+// last parent is taken off the queue, so each node and edge is visited once, however many paths
+// lead to it.
+fn create_bfs_order(proposition_graph: &PropositionGraph) -> Vec<PropositionNode> {
+    let mut roots: Vec<PropositionNode> = proposition_graph
+        .roots
+        .iter()
+        .map(PropositionNode::from_single)
+        .collect();
+    roots.sort_by_key(|node| node.debug_string());
+    let mut queue: VecDeque<PropositionNode> = roots.into_iter().collect();
+    let mut parents_waiting: HashMap<PropositionNode, usize> = HashMap::new();
+    let mut order = vec![];
+    while let Some(node) = queue.pop_front() {
+        for child in proposition_graph.get_all_forward(&node) {
+            let waiting = parents_waiting
+                .entry(child.clone())
+                .or_insert_with(|| proposition_graph.get_all_backward(&child).len());
+            *waiting -= 1;
+            if *waiting == 0 {
+                queue.push_back(child);
+            }
         }
-        seen.insert(node);
+        order.push(node);
     }
-    result.reverse();
-    result
+    order
 }
 
-fn create_bfs_order(proposition_graph: &PropositionGraph) -> Vec<PropositionNode> {
-    let mut queue = VecDeque::new();
-    let mut buffer = vec![];
-    for root in &proposition_graph.roots {
-        queue.push_back((0, PropositionNode::from_single(&root)));
-    }
-    while let Some((depth, node)) = queue.pop_front() {
-        buffer.push((depth, node.clone()));
-        let forward = proposition_graph.get_all_forward(&node);
-        for child in &forward {
-            queue.push_back((depth + 1, child.clone()));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{
+        fixtures::{jack, rule, WeightedWorld},
+        model::InferenceModel,
+    };
+    use std::cell::RefCell;
+
+    // Backimplications from (premise, conclusion) pairs, counting how often each is asked for.
+    struct CountingSource {
+        table: HashMap<Proposition, Vec<PropositionFactor>>,
+        calls: RefCell<HashMap<Proposition, usize>>,
+    }
+
+    impl CountingSource {
+        fn new(rules: &[(&str, &str)]) -> CountingSource {
+            let mut table: HashMap<Proposition, Vec<PropositionFactor>> = HashMap::new();
+            for (premise, conclusion) in rules {
+                table.entry(jack(conclusion)).or_default().push(PropositionFactor {
+                    premise: PropositionGroup::new(vec![jack(premise)]),
+                    conclusion: jack(conclusion),
+                    inference: rule(&[premise], conclusion),
+                });
+            }
+            CountingSource {
+                table,
+                calls: RefCell::new(HashMap::new()),
+            }
+        }
+    }
+
+    impl BackimplicationSource for CountingSource {
+        fn backimplications(&self, conclusion: &Proposition) -> Result<Vec<PropositionFactor>, Box<dyn Error>> {
+            *self.calls.borrow_mut().entry(conclusion.clone()).or_default() += 1;
+            self.table.backimplications(conclusion)
+        }
+    }
+
+    #[test]
+    fn diamond_expands_shared_ancestor_once() {
+        let source = CountingSource::new(&[("b", "d"), ("c", "d"), ("a", "b"), ("a", "c")]);
+        let graph = PropositionGraph::build(&source, &[jack("d")], &GraphBuildOptions::default()).unwrap();
+        assert_eq!(source.calls.borrow()[&jack("a")], 1);
+        assert_eq!(graph.roots, HashSet::from([jack("a")]));
+        // Four propositions and the premise groups {a}, {b} and {c}.
+        assert_eq!(graph.all_nodes.len(), 7);
+        let order = graph.get_bfs_order();
+        assert_eq!(order.first(), Some(&PropositionNode::from_single(&jack("a"))));
+        assert_eq!(order.last(), Some(&PropositionNode::from_single(&jack("d"))));
+    }
+
+    #[test]
+    fn bfs_order_is_linear_in_stacked_diamonds() {
+        // a0 -> {b0, c0} -> a1 -> ... -> a40, which has 2^40 paths from a0 to a40.
+        let depth = 40;
+        let names: Vec<[String; 4]> = (0..depth)
+            .map(|i| [format!("a{}", i), format!("b{}", i), format!("c{}", i), format!("a{}", i + 1)])
+            .collect();
+        let rules: Vec<(&str, &str)> = names
+            .iter()
+            .flat_map(|[a, b, c, next]| [(a.as_str(), b.as_str()), (a, c), (b, next), (c, next)])
+            .collect();
+        let source = CountingSource::new(&rules);
+        let graph = PropositionGraph::build(&source, &[jack(&format!("a{}", depth))], &GraphBuildOptions::default()).unwrap();
+        let order = graph.get_bfs_order();
+        assert_eq!(order.len(), graph.all_nodes.len());
+        let position: HashMap<&PropositionNode, usize> = order.iter().enumerate().map(|(i, node)| (node, i)).collect();
+        for node in &order {
+            for parent in graph.get_all_backward(node) {
+                assert!(position[&parent] < position[node]);
+            }
         }
     }
-    let result = reverse_prune_duplicates(&buffer);
-    result
+
+    #[test]
+    fn cycle_is_an_error_by_default() {
+        let source = CountingSource::new(&[("a", "b"), ("b", "a")]);
+        let result = PropositionGraph::build(&source, &[jack("a")], &GraphBuildOptions::default());
+        let message = result.err().unwrap().to_string();
+        assert!(message.contains("Cycle"), "{}", message);
+    }
+
+    #[test]
+    fn cycle_can_be_broken() {
+        let source = CountingSource::new(&[("a", "b"), ("b", "a")]);
+        let options = GraphBuildOptions {
+            cycle_policy: CyclePolicy::Break,
+            ..GraphBuildOptions::default()
+        };
+        let graph = PropositionGraph::build(&source, &[jack("a")], &options).unwrap();
+        assert_eq!(graph.roots, HashSet::from([jack("b")]));
+        assert_eq!(graph.get_all_backward(&PropositionNode::from_single(&jack("b"))).len(), 0);
+        assert_eq!(graph.get_all_backward(&PropositionNode::from_single(&jack("a"))).len(), 1);
+    }
+
+    #[test]
+    fn depth_budget_leaves_a_frontier_of_roots() {
+        let source = CountingSource::new(&[("c", "d"), ("b", "c"), ("a", "b")]);
+        let options = GraphBuildOptions {
+            max_depth: Some(2),
+            ..GraphBuildOptions::default()
+        };
+        let graph = PropositionGraph::build(&source, &[jack("d")], &options).unwrap();
+        assert!(!source.calls.borrow().contains_key(&jack("a")));
+        assert_eq!(graph.roots, HashSet::from([jack("b")]));
+        assert_eq!(graph.truncation.frontier, vec![jack("b")]);
+        assert!(graph.truncation.hit_depth_limit);
+        assert!(!graph.truncation.hit_node_limit);
+    }
+
+    #[test]
+    fn depth_budget_follows_the_shallowest_path() {
+        // The search reaches b first through c at depth 2, then directly from d at depth 1, so a
+        // is at depth 2 and only its premise z is past the limit.
+        let source = CountingSource::new(&[("c", "d"), ("b", "c"), ("b", "d"), ("a", "b"), ("z", "a")]);
+        let options = GraphBuildOptions {
+            max_depth: Some(3),
+            ..GraphBuildOptions::default()
+        };
+        let graph = PropositionGraph::build(&source, &[jack("d")], &options).unwrap();
+        assert_eq!(graph.truncation.frontier, vec![jack("z")]);
+        assert_eq!(graph.roots, HashSet::from([jack("z")]));
+        assert_eq!(graph.get_all_backward(&PropositionNode::from_single(&jack("a"))).len(), 1);
+    }
+
+    #[test]
+    fn targets_share_their_common_ancestor() {
+        let mut world = WeightedWorld::new(&[]);
+        world.link(&["a"], "b", 1f64);
+        world.link(&["a"], "c", 1f64);
+        let model = InferenceModel::new_shared(&world.resources).unwrap();
+        let graph =
+            PropositionGraph::new_shared_for_targets(model.graph.clone(), &[jack("b"), jack("c")], &GraphBuildOptions::default())
+                .unwrap();
+        assert_eq!(graph.targets, vec![jack("b"), jack("c")]);
+        // One node for a, and one premise group {a} with an edge to each target.
+        let a = PropositionGroup::new(vec![jack("a")]);
+        assert_eq!(graph.get_single_forward(&jack("a")), HashSet::from([a.clone()]));
+        assert_eq!(graph.group_forward[&a], HashSet::from([jack("b"), jack("c")]));
+        let order = graph.get_bfs_order();
+        let a_nodes = order.iter().filter(|node| **node == PropositionNode::from_single(&jack("a"))).count();
+        assert_eq!(a_nodes, 1);
+        assert_eq!(order.len(), graph.all_nodes.len());
+    }
 }
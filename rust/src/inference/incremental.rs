@@ -0,0 +1,137 @@
+use super::{inferencer::Inferencer, table::PropositionNode};
+use serde::Serialize;
+use std::{
+    collections::{HashSet, VecDeque},
+    error::Error,
+};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct IncrementalReport {
+    // How many times a dirty node was recomputed.
+    pub visits: usize,
+    // The largest message change left unpropagated because it was within `config.tolerance`.
+    pub residual: f64,
+    // False if the visit budget ran out while nodes were still dirty.
+    pub converged: bool,
+}
+
+fn message_change(old: Option<f64>, new: Option<f64>) -> f64 {
+    match (old, new) {
+        (Some(old), Some(new)) if (new - old).is_nan() => f64::INFINITY,
+        (Some(old), Some(new)) => (new - old).abs(),
+        _ => 1f64,
+    }
+}
+
+impl Inferencer {
+    // Recomputes `node`'s pi and lambda values and resends its messages in both directions.
+    // Returns each neighbor with how much its message from `node` changed, after normalizing.
+    pub fn refresh_node(&mut self, node: &PropositionNode) -> Result<Vec<(PropositionNode, f64)>, Box<dyn Error>> {
+        let children = self.proposition_graph.get_all_forward(node);
+        let parents = self.proposition_graph.get_all_backward(node);
+        let old_pi: Vec<Option<f64>> = children
+            .iter()
+            .map(|child| self.data.get_normalized_pi_message(node, child))
+            .collect();
+        let old_lambda: Vec<Option<f64>> = parents
+            .iter()
+            .map(|parent| self.data.get_normalized_lambda_message(node, parent))
+            .collect();
+        self.pi_update_value(node)?;
+        if self.intervention_for(node).is_some() {
+            self.lambda_set_from_intervention(node);
+        } else {
+            self.lambda_update_value(node)?;
+            self.lambda_send_messages(node)?;
+        }
+        self.pi_send_messages(node)?;
+        let mut changes = vec![];
+        for (child, old) in children.into_iter().zip(old_pi) {
+            let change = message_change(old, self.data.get_normalized_pi_message(node, &child));
+            changes.push((child, change));
+        }
+        for (parent, old) in parents.into_iter().zip(old_lambda) {
+            let change = message_change(old, self.data.get_normalized_lambda_message(node, &parent));
+            changes.push((parent, change));
+        }
+        Ok(changes)
+    }
+
+    // Brings an initialized chart up to date after evidence or interventions on `changed` were
+    // edited. Only nodes whose incoming messages moved by more than `config.tolerance` are
+    // revisited, so an edit costs time in the size of the neighborhood it actually affects.
+    // On loopy graphs the work is capped at `config.max_iterations` visits per node.
+    pub fn propagate_from_changes(&mut self, changed: &[PropositionNode]) -> Result<IncrementalReport, Box<dyn Error>> {
+        self.refresh_factor_cache()?;
+        let mut queue: VecDeque<PropositionNode> = changed.iter().cloned().collect();
+        let mut queued: HashSet<PropositionNode> = changed.iter().cloned().collect();
+        let budget = self.config.max_iterations * self.bfs_order.len().max(1);
+        let mut visits = 0;
+        let mut residual = 0f64;
+        while let Some(node) = queue.pop_front() {
+            if visits >= budget {
+                warn!(
+                    "incremental propagation stopped after {} visits with {} nodes still dirty",
+                    visits,
+                    queue.len() + 1
+                );
+                return Ok(IncrementalReport {
+                    visits,
+                    residual,
+                    converged: false,
+                });
+            }
+            queued.remove(&node);
+            visits += 1;
+            let changes = self.refresh_node(&node)?;
+            // As in `propagate_by_residuals`, a damped node still owes part of its update.
+            let largest = changes.iter().map(|(_, change)| *change).fold(0f64, f64::max);
+            let owed = (node.clone(), largest * self.config.damping / (1f64 - self.config.damping));
+            for (next, change) in changes.into_iter().chain(std::iter::once(owed)) {
+                if change <= self.config.tolerance {
+                    residual = residual.max(change);
+                } else if queued.insert(next.clone()) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        trace!("propagate_from_changes visits {} residual {}", visits, residual);
+        Ok(IncrementalReport {
+            visits,
+            residual,
+            converged: true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::{jack, WeightedWorld};
+
+    #[test]
+    fn incremental_update_matches_full_recompute() {
+        for damping in ["0", "0.3"] {
+            let mut world = WeightedWorld::new(&["--damping", damping]);
+            for (premise, conclusion, weight) in [("a", "b", 1f64), ("b", "c", 2f64), ("a", "d", -1f64), ("e", "c", 0.5)] {
+                world.link(&[premise], conclusion, weight);
+            }
+            world.observe("c", 1f64);
+            let targets = ["c", "d"];
+            let mut inferencer = world.inferencer(&targets);
+            inferencer.propagate_until_converged(None, &mut |_inferencer| Ok(())).unwrap();
+            // Evidence edited in the shared fact store after the chart was built.
+            world.observe("d", 0f64);
+            let report = inferencer
+                .propagate_from_changes(&[PropositionNode::from_single(&jack("d"))])
+                .unwrap();
+            assert!(report.converged);
+            let mut recomputed = world.inferencer(&targets);
+            recomputed.propagate_until_converged(None, &mut |_inferencer| Ok(())).unwrap();
+            let incremental = inferencer.build_marginal_table().unwrap();
+            let full = recomputed.build_marginal_table().unwrap();
+            assert!(incremental.max_difference(&full) < 1e-5, "{} {:?} {:?}", damping, incremental, full);
+            assert!((inferencer.get_marginal(&PropositionNode::from_single(&jack("a"))).unwrap() - 0.5).abs() > 0.1);
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use super::{
+    convergence::MessageSchedule,
+    factor_cache::FactorCache,
     graph::{PropositionFactor, PropositionGraph},
-    table::{HashMapBeliefTable, PropositionNode},
+    table::{log_normalize, HashMapBeliefTable, PropositionNode},
 };
 use crate::{
     common::{
-        interface::BeliefTable,
+        interface::{BeliefTable, ExplainStatistics},
         model::{FactorContext, InferenceModel},
         proposition_db,
         setup::ConfigurationOptions,
@@ -20,7 +22,7 @@ use colored::*;
 use redis::Connection;
 use serde::{Deserialize, Serialize};
 use std::{
-    borrow::Borrow, collections::{HashMap, HashSet, VecDeque}, error::Error, fs::OpenOptions, rc::Rc
+    borrow::Borrow, cell::RefCell, collections::{HashMap, HashSet, VecDeque}, error::Error, fs::OpenOptions, rc::Rc
 };
 use std::io::Write;
 
@@ -30,16 +32,53 @@ pub struct Inferencer {
     pub config: ConfigurationOptions,
     pub model: Rc<InferenceModel>,
     pub fact_memory: Rc<dyn BeliefTable>,
+    // do(proposition = value), kept apart from the evidence in `fact_memory`.
+    pub interventions: HashMap<Proposition, bool>,
     pub proposition_graph: Rc<PropositionGraph>,
     pub data: HashMapBeliefTable,
     pub bfs_order: Vec<PropositionNode>,
+    pub schedule: MessageSchedule,
+    pub factor_cache: RefCell<FactorCache>,
 }
 
-#[derive(Serialize, Deserialize)]
+// Virtual evidence with likelihood ratio `ratio`, as a normalized likelihood `[1 - e, e]`.
+pub fn likelihood_from_ratio(ratio: f64) -> [f64; 2] {
+    [1f64 / (1f64 + ratio), ratio / (1f64 + ratio)]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MarginalTable {
     entries: Vec<(String, f64)>,
 }
 
+impl MarginalTable {
+    pub fn new(entries: Vec<(String, f64)>) -> MarginalTable {
+        MarginalTable { entries }
+    }
+
+    pub fn entries(&self) -> &Vec<(String, f64)> {
+        &self.entries
+    }
+
+    pub fn get(&self, node_string: &str) -> Option<f64> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name == node_string)
+            .map(|(_, probability)| *probability)
+    }
+
+    // The largest absolute difference between two tables, over the entries they share.
+    pub fn max_difference(&self, other: &MarginalTable) -> f64 {
+        let mut result = 0f64;
+        for (name, probability) in &self.entries {
+            if let Some(other_probability) = other.get(name) {
+                result = result.max((probability - other_probability).abs());
+            }
+        }
+        result
+    }
+}
+
 impl Inferencer {
     pub fn new_mutable(
         config: &ConfigurationOptions,
@@ -52,25 +91,32 @@ impl Inferencer {
             config: config.clone(),
             model,
             fact_memory,
+            interventions: HashMap::new(),
             proposition_graph,
             data: HashMapBeliefTable::new(bfs_order.clone()),
             bfs_order,
+            schedule: MessageSchedule::from_config(config),
+            factor_cache: RefCell::new(FactorCache::default()),
         }))
     }
 
     pub fn initialize_chart(&mut self) -> Result<(), Box<dyn Error>> {
+        self.refresh_factor_cache()?;
+        self.warn_about_sampled_factors()?;
         self.initialize_lambda()?;
         self.do_pi_traversal()?;
         Ok(())
     }
 
     pub fn do_full_forward_and_backward(&mut self) -> Result<(), Box<dyn Error>> {
+        self.refresh_factor_cache()?;
         self.do_pi_traversal()?;
         self.do_lambda_traversal()?;
         Ok(())
     }
 
     pub fn do_fan_out_from_node(&mut self, node: &PropositionNode) -> Result<(), Box<dyn Error>> {
+        self.refresh_factor_cache()?;
         let mut backward_order = self.bfs_order.clone();
         backward_order.reverse();
         let mut started = false;
@@ -93,15 +139,8 @@ impl Inferencer {
         println!("\nMARGINALS");
         let mut entries = vec![];
         for node in &self.bfs_order {
-            let pi0 = self.data.get_pi_value(node, 0).unwrap();
-            let pi1 = self.data.get_pi_value(node, 1).unwrap();
-            let lambda0 = self.data.get_lambda_value(node, 0).unwrap();
-            let lambda1 = self.data.get_lambda_value(node, 1).unwrap();
-            let potential0 = pi0 * lambda0;
-            let potential1 = pi1 * lambda1;
-            let norm = potential0 + potential1;
-            let probability0 = potential0 / norm;
-            let probability1 = potential1 / norm;
+            let probability1 = self.get_marginal(node)?;
+            let probability0 = 1f64 - probability1;
 
             let formatted_prob0 = format!("{:.8}", probability0);
             let formatted_prob1 = format!("{:.8}", probability1);
@@ -121,24 +160,22 @@ impl Inferencer {
         Ok(table)
     }
 
+    // Normalizes pi * lambda in log space, so beliefs that underflow as probabilities still compare.
+    pub fn get_marginal(&self, node: &PropositionNode) -> Result<f64, Box<dyn Error>> {
+        let missing = || format!("No pi or lambda value for {:?}.", node);
+        let log_pi0 = self.data.get_log_pi_value(node, 0).ok_or_else(missing)?;
+        let log_pi1 = self.data.get_log_pi_value(node, 1).ok_or_else(missing)?;
+        let log_lambda0 = self.data.get_log_lambda_value(node, 0).ok_or_else(missing)?;
+        let log_lambda1 = self.data.get_log_lambda_value(node, 1).ok_or_else(missing)?;
+        let [_, log_probability1] = log_normalize([log_pi0 + log_lambda0, log_pi1 + log_lambda1]);
+        Ok(log_probability1.exp())
+    }
+
     pub fn build_marginal_table(&self) -> Result<MarginalTable, Box<dyn Error>> {
         let mut entries = vec![];
         for node in &self.bfs_order {
-            let pi0 = self.data.get_pi_value(node, 0).unwrap();
-            let pi1 = self.data.get_pi_value(node, 1).unwrap();
-            let lambda0 = self.data.get_lambda_value(node, 0).unwrap();
-            let lambda1 = self.data.get_lambda_value(node, 1).unwrap();
-            let potential0 = pi0 * lambda0;
-            let potential1 = pi1 * lambda1;
-            let norm = potential0 + potential1;
-            let probability0 = potential0 / norm;
-            let probability1 = potential1 / norm;
-
-            let formatted_prob0 = format!("{:.8}", probability0);
-            let formatted_prob1 = format!("{:.8}", probability1);
             let node_string = format!("{:?}", node);
-            let probability = probability1;
-            entries.push((node_string, probability));
+            entries.push((node_string, self.get_marginal(node)?));
         }
         let table = MarginalTable { entries };
         Ok(table)
@@ -169,8 +206,8 @@ impl Inferencer {
     pub fn is_root(&self, node: &PropositionNode) -> bool {
         if node.is_single() {
             let as_single = node.extract_single();
-            let is_root = self.proposition_graph.roots.contains(&as_single);
-            is_root
+            
+            self.proposition_graph.roots.contains(&as_single)
         } else {
             false
         }
@@ -208,6 +245,42 @@ impl Inferencer {
         }
     }
 
+    // The likelihood ratio of virtual evidence on `node`, unless hard evidence or an intervention
+    // overrides it.
+    pub fn virtual_evidence_for(&self, node: &PropositionNode) -> Result<Option<f64>, Box<dyn Error>> {
+        if !node.is_single() || self.intervention_for(node).is_some() || self.is_observed(node)? {
+            return Ok(None);
+        }
+        self.fact_memory.get_likelihood_ratio(&node.extract_single())
+    }
+
+    // Attributes the conclusion's probability to the features of its backimplications, with each
+    // premise group set to its current expected value from the incoming pi messages.
+    pub fn explain_conclusion(&self, node: &PropositionNode) -> Result<ExplainStatistics, Box<dyn Error>> {
+        if !node.is_single() {
+            return Err(format!("Only a single proposition can be explained, got {:?}.", node).into());
+        }
+        let conclusion = node.extract_single();
+        let mut factors = vec![];
+        let mut probabilities = vec![];
+        for premise in &self.proposition_graph.get_all_backward(node) {
+            let missing = || format!("No pi message from {:?} to {:?}.", premise, node);
+            probabilities.push(self.data.get_normalized_pi_message(premise, node).ok_or_else(missing)?);
+            let group = premise.extract_group();
+            let inference = self.proposition_graph.get_inference_used(&group, &conclusion);
+            factors.push(PropositionFactor {
+                premise: group,
+                conclusion: conclusion.clone(),
+                inference,
+            });
+        }
+        let context = FactorContext {
+            factor: factors,
+            probabilities,
+        };
+        self.model.model.explain(&context)
+    }
+
     pub fn score_factor_assignment(
         &self,
         premises: &Vec<PropositionNode>,
@@ -227,30 +300,32 @@ impl Inferencer {
         premise_assignment: &HashMap<PropositionNode, bool>,
         conclusion: &PropositionNode,
     ) -> Result<f64, Box<dyn Error>> {
-        let mut proposition_premises = vec![];
-        for node_premise in premises {
-            proposition_premises.push(node_premise.extract_group());
-        }
-        let proposition_conclusion = conclusion.extract_single();
-        let context = build_factor_context_for_assignment(
-            &self.proposition_graph,
-            &proposition_premises,
-            premise_assignment,
-            &proposition_conclusion,
-        );
-        let statistics = self.model.model.predict(&context)?;
-        info!("score_factor_assignment_disjunction; premises: {:?}, assignment: {:?}, conclusion {:?}, probability {}", premises, premise_assignment, conclusion, statistics.probability);
-        Ok(statistics.probability)
+        self.cached_prediction(premises, premise_assignment, conclusion, || {
+            let mut proposition_premises = vec![];
+            for node_premise in premises {
+                proposition_premises.push(node_premise.extract_group());
+            }
+            let proposition_conclusion = conclusion.extract_single();
+            let context = build_factor_context_for_assignment(
+                &self.proposition_graph,
+                &proposition_premises,
+                premise_assignment,
+                &proposition_conclusion,
+            );
+            let statistics = self.model.model.predict(&context)?;
+            info!("score_factor_assignment_disjunction; premises: {:?}, assignment: {:?}, conclusion {:?}, probability {}", premises, premise_assignment, conclusion, statistics.probability);
+            Ok(statistics.probability)
+        })
     }
 
     pub fn score_factor_assignment_conjunction(
         &self,
-        premises: &Vec<PropositionNode>,
+        premises: &[PropositionNode],
         premise_assignment: &HashMap<PropositionNode, bool>,
         conclusion: &PropositionNode,
     ) -> Result<f64, Box<dyn Error>> {
         let mut and_result = true;
-        for (_node, value) in premise_assignment {
+        for value in premise_assignment.values() {
             and_result &= *value;
         }
         let result = if and_result { 1f64 } else { 0f64 };
@@ -282,11 +357,11 @@ pub fn build_factor_context_for_assignment(
         };
         factors.push(factor);
     }
-    let context = FactorContext {
+    
+    FactorContext {
         factor: factors,
         probabilities,
-    };
-    context
+    }
 }
 
 pub fn compute_each_combination(
@@ -297,8 +372,7 @@ pub fn compute_each_combination(
     let mut all_combinations = Vec::new();
     for i in 0..(1 << n) {
         let mut current_combination = HashMap::new();
-        for j in 0..n {
-            let prop = &propositions[j];
+        for (j, prop) in propositions.iter().enumerate() {
             let state = i & (1 << j) != 0;
             current_combination.insert(prop.clone(), state);
         }
@@ -0,0 +1,116 @@
+use super::{inferencer::Inferencer, table::PropositionNode};
+use crate::{model::objects::Proposition, model::weights::CLASS_LABELS};
+use std::error::Error;
+
+// Interventions, do(proposition = value), are kept apart from the evidence in `fact_memory`.
+// An intervened proposition is clamped to its value and its incoming factors are cut: it still
+// sends pi messages to its children, but sends uninformative lambda messages to its premises,
+// so nothing learned below it changes beliefs about its causes. An intervention overrides any
+// evidence on the same proposition.
+impl Inferencer {
+    pub fn set_intervention(&mut self, proposition: &Proposition, value: bool) -> Result<(), Box<dyn Error>> {
+        if !self
+            .proposition_graph
+            .all_nodes
+            .contains(&PropositionNode::from_single(proposition))
+        {
+            return Err(format!("Cannot intervene on {:?}, which is not in the graph.", proposition).into());
+        }
+        self.interventions.insert(proposition.clone(), value);
+        Ok(())
+    }
+
+    pub fn clear_intervention(&mut self, proposition: &Proposition) {
+        self.interventions.remove(proposition);
+    }
+
+    pub fn clear_interventions(&mut self) {
+        self.interventions.clear();
+    }
+
+    pub fn intervention_for(&self, node: &PropositionNode) -> Option<bool> {
+        if node.is_single() {
+            self.interventions.get(&node.extract_single()).copied()
+        } else {
+            None
+        }
+    }
+
+    pub fn pi_set_from_intervention(&mut self, node: &PropositionNode, value: bool) {
+        let probability = if value { 1f64 } else { 0f64 };
+        self.data.set_pi_value(node, 1, probability);
+        self.data.set_pi_value(node, 0, 1f64 - probability);
+    }
+
+    // The clamped pi value already fixes the belief, so lambda is left uninformative, as are
+    // the messages to the cut premises.
+    pub fn lambda_set_from_intervention(&mut self, node: &PropositionNode) {
+        for class_label in CLASS_LABELS {
+            self.data.set_lambda_value(node, class_label, 1f64);
+            for parent in &self.proposition_graph.get_all_backward(node) {
+                self.data.set_lambda_message(node, parent, class_label, 1f64);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::fixtures::{jack, WeightedWorld};
+    use crate::inference::{inferencer::Inferencer, table::PropositionNode};
+
+    // a -> b -> c with c observed false and a ratio of 3/2 on a. Under do(b = 1) the evidence on c
+    // no longer reaches a, so P(a) = 3/5.
+    fn world() -> WeightedWorld {
+        let mut world = WeightedWorld::new(&["--seed", "7", "--gibbs_samples", "4000", "--importance_samples", "20000"]);
+        world.link(&["a"], "b", 2f64);
+        world.link(&["b"], "c", 2f64);
+        world.observe("c", 0f64);
+        world.likelihood_ratio("a", 1.5);
+        world
+    }
+
+    fn intervened(world: &WeightedWorld) -> Box<Inferencer> {
+        let mut inferencer = world.inferencer(&["c"]);
+        inferencer.set_intervention(&jack("b"), true).unwrap();
+        inferencer
+    }
+
+    fn name(function: &str) -> String {
+        format!("{:?}", PropositionNode::from_single(&jack(function)))
+    }
+
+    #[test]
+    fn samplers_cut_the_intervened_factor() {
+        let world = world();
+        let inferencer = intervened(&world);
+        let exact = inferencer.compute_exact_marginals().unwrap();
+        assert!((exact.get(&name("a")).unwrap() - 0.6).abs() < 1e-12);
+        assert_eq!(exact.get(&name("b")), Some(1f64));
+
+        let gibbs = inferencer.gibbs_sample().unwrap();
+        let diagnostic = gibbs.diagnostics.iter().find(|diagnostic| diagnostic.node == name("a")).unwrap();
+        let estimate = gibbs.marginals.get(&name("a")).unwrap();
+        assert!((estimate - 0.6).abs() < 4f64 * diagnostic.standard_error, "{} {:?}", estimate, diagnostic);
+        assert_eq!(gibbs.marginals.get(&name("b")), Some(1f64));
+
+        let weighted = inferencer.likelihood_weighting(&mut |_report| Ok(true)).unwrap();
+        let estimate = weighted.marginals.get(&name("a")).unwrap();
+        let standard_error = (0.24 / weighted.effective_sample_size).sqrt();
+        assert!((estimate - 0.6).abs() < 4f64 * standard_error, "{} {}", estimate, standard_error);
+        assert_eq!(weighted.marginals.get(&name("b")), Some(1f64));
+    }
+
+    #[test]
+    fn explanation_cuts_the_intervened_factor() {
+        let world = world();
+        // Without the intervention, c = 0 is best explained by b = 0 and so a = 0.
+        let observed = world.inferencer(&["c"]).most_probable_explanation().unwrap();
+        assert_eq!(observed.assignment, vec![(name("a"), false), (name("b"), false)]);
+        let explanation = intervened(&world).most_probable_explanation().unwrap();
+        assert_eq!(explanation.assignment, vec![(name("a"), true)]);
+        // The prior and likelihood of a, and P(c = 0 | b = 1) = sigmoid(-4).
+        let expected = (0.5f64 * 0.6).ln() - (1f64 + 4f64.exp()).ln();
+        assert!((explanation.log_probability - expected).abs() < 1e-9, "{}", explanation.log_probability);
+    }
+}
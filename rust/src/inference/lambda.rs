@@ -1,9 +1,9 @@
 use super::{
-    inference::{compute_each_combination, groups_from_backlinks, Inferencer},
-    table::{GenericNodeType, PropositionNode},
+    inferencer::{compute_each_combination, groups_from_backlinks, Inferencer},
+    table::{log_normalize, log_sum_exp, GenericNodeType, PropositionNode},
 };
 use crate::{
-    inference::inference::build_factor_context_for_assignment,
+    inference::inferencer::build_factor_context_for_assignment,
     model::{objects::EXISTENCE_FUNCTION, weights::CLASS_LABELS},
     print_blue, print_green, print_red, print_yellow,
 };
@@ -43,17 +43,22 @@ impl Inferencer {
     }
 
     pub fn lambda_visit_node(&mut self, from_node: &PropositionNode) -> Result<(), Box<dyn Error>> {
-        self.lambda_send_messages(from_node)?;
-        let is_observed = self.is_observed(from_node)?;
-        trace!(
-            "lambda_visit_node {:?} is_observed {}",
-            from_node,
-            is_observed
-        );
+        if self.intervention_for(from_node).is_some() {
+            self.lambda_set_from_intervention(from_node);
+            return Ok(());
+        }
+        self.lambda_update_value(from_node)?;
+        self.lambda_send_messages(from_node)
+    }
+
+    // Callers handle intervened nodes with `lambda_set_from_intervention`.
+    pub fn lambda_update_value(&mut self, node: &PropositionNode) -> Result<(), Box<dyn Error>> {
+        let is_observed = self.is_observed(node)?;
+        trace!("lambda_update_value {:?} is_observed {}", node, is_observed);
         if is_observed {
-            self.lambda_set_from_evidence(from_node)?;
+            self.lambda_set_from_evidence(node)?;
         } else {
-            self.lambda_compute_value(&from_node)?;
+            self.lambda_compute_value(node)?;
         }
         Ok(())
     }
@@ -81,17 +86,25 @@ impl Inferencer {
         let is_observed = self.is_observed(node)?;
         assert!(!is_observed);
         let children = self.proposition_graph.get_all_forward(node);
+        let mut log_values = [0f64; 2];
         for class_label in &CLASS_LABELS {
-            let mut product = 1f64;
-            for (_child_index, child_node) in children.iter().enumerate() {
+            let mut log_product = 0f64;
+            for child_node in children.iter() {
                 let child_lambda = self
                     .data
-                    .get_lambda_message(&child_node, node,*class_label)
+                    .get_log_lambda_message(child_node, node, *class_label)
                     .unwrap();
-                product *= child_lambda;
+                log_product += child_lambda;
             }
+            log_values[*class_label] = log_product;
+        }
+        if let Some(ratio) = self.virtual_evidence_for(node)? {
+            log_values[1] += ratio.ln();
+        }
+        let log_values = log_normalize(log_values);
+        for class_label in &CLASS_LABELS {
             self.data
-                .set_lambda_value(&node, *class_label, product);
+                .set_log_lambda_value(node, *class_label, log_values[*class_label]);
         }
         Ok(())
     }
@@ -99,22 +112,32 @@ impl Inferencer {
     pub fn lambda_send_messages(&mut self, node: &PropositionNode) -> Result<(), Box<dyn Error>> {
         let parent_nodes = self.proposition_graph.get_all_backward(node);
         trace!("lambda_send_generic for node {:?} with parents {:?}", node, &parent_nodes);
+        let log_lambda_true = self.data.get_log_lambda_value(node, 1).unwrap();
+        let log_lambda_false = self.data.get_log_lambda_value(node, 0).unwrap();
+        if let Some(log_messages) = self.lambda_messages_without_enumeration(
+            &parent_nodes,
+            node,
+            [log_lambda_false, log_lambda_true],
+        )? {
+            for (to_parent, log_message) in parent_nodes.iter().zip(log_messages) {
+                self.data.send_log_lambda_message(node, to_parent, log_message, self.config.damping);
+            }
+            return Ok(());
+        }
         let all_combinations = compute_each_combination(&parent_nodes);
-        let lambda_true = self.data.get_lambda_value(node, 1).unwrap();
-        let lambda_false = self.data.get_lambda_value(node, 0).unwrap();
         for (to_index, to_parent) in parent_nodes.iter().enumerate() {
             trace!("to_index {} to_parent {:?}", to_index, to_parent);
-            let mut sum_true = 0f64;
-            let mut sum_false = 0f64;
+            let mut log_terms_true = vec![];
+            let mut log_terms_false = vec![];
             for combination in &all_combinations {
-                let mut pi_product = 1f64;
+                let mut log_pi_product = 0f64;
                 for (other_index, other_parent) in parent_nodes.iter().enumerate() {
                     if other_index != to_index {
                         let class_bool = combination.get(other_parent).unwrap();
                         let class_label = if *class_bool { 1 } else { 0 };
-                        let this_pi = self.data.get_pi_message(&other_parent, node, class_label).unwrap();
-                        trace!("using pi message parent {:?}, node {:?}, label {}: pi={}", &other_parent, node, class_label, this_pi);
-                        pi_product *= this_pi;
+                        let this_pi = self.data.get_log_pi_message(other_parent, node, class_label).unwrap();
+                        trace!("using pi message parent {:?}, node {:?}, label {}: log pi={}", &other_parent, node, class_label, this_pi);
+                        log_pi_product += this_pi;
                     }
                 }
                 let probability_true =
@@ -122,18 +145,19 @@ impl Inferencer {
                 let probability_false = 1f64 - probability_true;
                 trace!("probability {} for {:?} on assignment {:?}", probability_true, node, combination);
                 let parent_assignment = combination.get(to_parent).unwrap();
-                let true_factor = probability_true * pi_product * lambda_true;
-                let false_factor = probability_false * pi_product * lambda_false;
+                let log_true_factor = probability_true.ln() + log_pi_product + log_lambda_true;
+                let log_false_factor = probability_false.ln() + log_pi_product + log_lambda_false;
                 if *parent_assignment {
-                    sum_true += true_factor + false_factor;
+                    log_terms_true.push(log_true_factor);
+                    log_terms_true.push(log_false_factor);
                 } else {
-                    sum_false += true_factor + false_factor;
+                    log_terms_false.push(log_true_factor);
+                    log_terms_false.push(log_false_factor);
                 }
             }
-            trace!("final 1 lambda message {} from {:?} to {:?}", sum_true, node, to_parent);
-            trace!("final 0 lambda message {} from {:?} to {:?}", sum_false, node, to_parent);
-            self.data.set_lambda_message(node, to_parent, 1, sum_true);
-            self.data.set_lambda_message(node, to_parent, 0, sum_false);
+            let log_message = [log_sum_exp(&log_terms_false), log_sum_exp(&log_terms_true)];
+            trace!("final log lambda message {:?} from {:?} to {:?}", log_message, node, to_parent);
+            self.data.send_log_lambda_message(node, to_parent, log_message, self.config.damping);
         }
         Ok(())
     }
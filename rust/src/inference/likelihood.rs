@@ -0,0 +1,197 @@
+use super::{
+    inferencer::{Inferencer, MarginalTable},
+    sampling::SamplingNetwork,
+};
+use crate::common::random::random_f64;
+use serde::Serialize;
+use std::error::Error;
+
+// How many samples are drawn between calls to the progress callback.
+const REPORT_INTERVAL: usize = 1000;
+
+// Sees the estimate so far; returning `false` stops sampling early.
+pub type ImportanceCallback<'a> = dyn FnMut(&ImportanceReport) -> Result<bool, Box<dyn Error>> + 'a;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportanceReport {
+    pub marginals: MarginalTable,
+    pub samples: usize,
+    // Kish's effective sample size, (sum w)^2 / sum w^2.
+    pub effective_sample_size: f64,
+}
+
+struct WeightedTotals {
+    true_weight: Vec<f64>,
+    total_weight: f64,
+    total_squared_weight: f64,
+    samples: usize,
+}
+
+impl WeightedTotals {
+    fn add(&mut self, state: &[bool], weight: f64) {
+        for (index, value) in state.iter().enumerate() {
+            if *value {
+                self.true_weight[index] += weight;
+            }
+        }
+        self.total_weight += weight;
+        self.total_squared_weight += weight * weight;
+        self.samples += 1;
+    }
+
+    fn effective_sample_size(&self) -> f64 {
+        self.total_weight * self.total_weight / self.total_squared_weight
+    }
+
+    fn report(&self, network: &SamplingNetwork) -> ImportanceReport {
+        let entries = network
+            .nodes
+            .iter()
+            .zip(&self.true_weight)
+            .map(|(node, weight)| (format!("{:?}", node), weight / self.total_weight))
+            .collect();
+        ImportanceReport {
+            marginals: MarginalTable::new(entries),
+            samples: self.samples,
+            effective_sample_size: self.effective_sample_size(),
+        }
+    }
+}
+
+impl<'a> SamplingNetwork<'a> {
+    // Samples every node forward in `bfs_order` and returns the sample's importance weight.
+    // An observed node is drawn from its conditional times the evidence likelihood `[1 - e, e]`,
+    // and the weight picks up that product's normalizer; for 0/1 evidence this clamps the node.
+    fn draw_weighted(&mut self, state: &mut [bool]) -> Result<f64, Box<dyn Error>> {
+        let mut weight = 1f64;
+        for node in 0..self.nodes.len() {
+            if self.is_group[node] {
+                state[node] = self.group_value(node, state);
+                continue;
+            }
+            let probability = self.probability_true(node, state)?;
+            let probability = match self.evidence[node] {
+                Some(evidence) => {
+                    let weight_true = probability * evidence;
+                    let norm = weight_true + (1f64 - probability) * (1f64 - evidence);
+                    if norm <= 0f64 {
+                        return Ok(0f64);
+                    }
+                    weight *= norm;
+                    weight_true / norm
+                }
+                None => probability,
+            };
+            state[node] = random_f64() < probability;
+        }
+        Ok(weight)
+    }
+}
+
+impl Inferencer {
+    // Likelihood weighting: draws up to `config.importance_samples` forward samples, weighted by
+    // the evidence in `fact_memory`. `on_progress` sees the estimate every `REPORT_INTERVAL`
+    // samples, and can stop the run early.
+    pub fn likelihood_weighting(
+        &self,
+        on_progress: &mut ImportanceCallback,
+    ) -> Result<ImportanceReport, Box<dyn Error>> {
+        let mut network = SamplingNetwork::new(self)?;
+        let mut totals = WeightedTotals {
+            true_weight: vec![0f64; network.nodes.len()],
+            total_weight: 0f64,
+            total_squared_weight: 0f64,
+            samples: 0,
+        };
+        let mut state = vec![false; network.nodes.len()];
+        while totals.samples < self.config.importance_samples {
+            let weight = network.draw_weighted(&mut state)?;
+            totals.add(&state, weight);
+            if totals.samples.is_multiple_of(REPORT_INTERVAL)
+                && totals.total_weight > 0f64
+                && !on_progress(&totals.report(&network))?
+            {
+                break;
+            }
+        }
+        if totals.total_weight <= 0f64 {
+            return Err(format!(
+                "All {} likelihood weighting samples have zero weight under the evidence.",
+                totals.samples
+            )
+            .into());
+        }
+        let report = totals.report(&network);
+        trace!(
+            "likelihood_weighting samples {} effective_sample_size {}",
+            report.samples,
+            report.effective_sample_size
+        );
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::WeightedWorld;
+
+    fn totals_with_weights(weights: &[f64]) -> WeightedTotals {
+        let mut totals = WeightedTotals {
+            true_weight: vec![0f64],
+            total_weight: 0f64,
+            total_squared_weight: 0f64,
+            samples: 0,
+        };
+        for weight in weights {
+            totals.add(&[true], *weight);
+        }
+        totals
+    }
+
+    #[test]
+    fn effective_sample_size_of_known_weights() {
+        assert!((totals_with_weights(&[0.3; 50]).effective_sample_size() - 50f64).abs() < 1e-9);
+        // One sample carrying all the weight is worth one sample.
+        let mut weights = vec![0f64; 49];
+        weights.push(2f64);
+        assert!((totals_with_weights(&weights).effective_sample_size() - 1f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn matches_exact_under_hard_and_virtual_evidence() {
+        let mut world = WeightedWorld::new(&["--seed", "11", "--importance_samples", "20000"]);
+        for (premise, conclusion, weight) in [("a", "b", 1f64), ("b", "c", -0.5), ("a", "d", 0.8)] {
+            world.link(&[premise], conclusion, weight);
+        }
+        world.observe("c", 1f64);
+        world.likelihood_ratio("d", 4f64);
+        let inferencer = world.inferencer(&["c", "d"]);
+        let exact = inferencer.compute_exact_marginals().unwrap();
+        let report = inferencer.likelihood_weighting(&mut |_report| Ok(true)).unwrap();
+        assert_eq!(report.samples, 20000);
+        // The hard evidence on c makes the weights uneven.
+        assert!(report.effective_sample_size < report.samples as f64);
+        for (node, probability) in exact.entries() {
+            let estimate = report.marginals.get(node).unwrap();
+            let standard_error = (probability * (1f64 - probability) / report.effective_sample_size).sqrt();
+            assert!(
+                (estimate - probability).abs() < 4f64 * standard_error + 1e-9,
+                "{} {} {} {}",
+                node,
+                estimate,
+                probability,
+                standard_error
+            );
+        }
+    }
+
+    #[test]
+    fn without_evidence_every_sample_counts() {
+        let mut world = WeightedWorld::new(&["--seed", "11", "--importance_samples", "1000"]);
+        world.link(&["a"], "b", 1f64);
+        let inferencer = world.inferencer(&["b"]);
+        let report = inferencer.likelihood_weighting(&mut |_report| Ok(true)).unwrap();
+        assert!((report.effective_sample_size - 1000f64).abs() < 1e-6);
+    }
+}
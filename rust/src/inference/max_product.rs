@@ -0,0 +1,323 @@
+use super::{
+    convergence::{ConvergenceReport, MessageSchedule},
+    inferencer::{compute_each_combination, likelihood_from_ratio, Inferencer},
+    table::{log_normalize, HashMapBeliefTable, PropositionNode},
+};
+use crate::model::weights::CLASS_LABELS;
+use serde::Serialize;
+use std::{collections::HashMap, error::Error};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MostProbableExplanation {
+    // Every single proposition neither observed nor intervened on, with its value in the
+    // explanation, in `bfs_order`.
+    pub assignment: Vec<(String, bool)>,
+    // log P(assignment, evidence), with each observation entering as a likelihood `[1 - e, e]`
+    // and virtual evidence as its normalized likelihood ratio, in the graph where intervened
+    // nodes have lost their incoming factors.
+    pub log_probability: f64,
+    pub probability: f64,
+    pub convergence: ConvergenceReport,
+}
+
+// For each node and value, the parent assignment that achieved its max-product pi value.
+type BackPointers = HashMap<(PropositionNode, usize), HashMap<PropositionNode, bool>>;
+
+// `[log(1 - p), log(p)]`.
+fn log_pair(probability: f64) -> [f64; 2] {
+    [(1f64 - probability).ln(), probability.ln()]
+}
+
+impl Inferencer {
+    // Evidence on an intervened node is overridden by the intervention.
+    fn evidence_for(&self, node: &PropositionNode) -> Result<Option<f64>, Box<dyn Error>> {
+        if node.is_single() && self.intervention_for(node).is_none() {
+            self.fact_memory.get_proposition_probability(&node.extract_single())
+        } else {
+            Ok(None)
+        }
+    }
+
+    // The likelihood of the virtual evidence on `node`, flat if it has none.
+    fn virtual_likelihood(&self, node: &PropositionNode) -> Result<[f64; 2], Box<dyn Error>> {
+        Ok(match self.virtual_evidence_for(node)? {
+            Some(ratio) => likelihood_from_ratio(ratio),
+            None => [1f64, 1f64],
+        })
+    }
+
+    // As `pi_visit_node`, with the sum over parent assignments replaced by a max. Works in log
+    // space, like the sum-product passes, so long chains do not underflow.
+    fn max_pi_visit_node(
+        &self,
+        table: &mut HashMapBeliefTable,
+        back_pointers: &mut BackPointers,
+        node: &PropositionNode,
+    ) -> Result<(), Box<dyn Error>> {
+        let log_pi = if let Some(value) = self.intervention_for(node) {
+            log_pair(if value { 1f64 } else { 0f64 })
+        } else if let Some(evidence) = self.evidence_for(node)? {
+            log_pair(evidence)
+        } else if self.is_root(node) {
+            log_pair(self.model.priors.prior_for_proposition(&node.extract_single())?)
+        } else {
+            let parent_nodes = self.proposition_graph.get_all_backward(node);
+            let mut best = [f64::NEG_INFINITY; 2];
+            let mut best_combination: [Option<HashMap<PropositionNode, bool>>; 2] = [None, None];
+            for combination in compute_each_combination(&parent_nodes) {
+                let mut log_product = 0f64;
+                for parent_node in &parent_nodes {
+                    let outcome = if combination[parent_node] { 1 } else { 0 };
+                    log_product += table.get_log_pi_message(parent_node, node, outcome).unwrap();
+                }
+                let true_marginal = self.score_factor_assignment(&parent_nodes, &combination, node)?;
+                let scores = [(1f64 - true_marginal).ln() + log_product, true_marginal.ln() + log_product];
+                for class_label in CLASS_LABELS {
+                    if best_combination[class_label].is_none() || scores[class_label] > best[class_label] {
+                        best[class_label] = scores[class_label];
+                        best_combination[class_label] = Some(combination.clone());
+                    }
+                }
+            }
+            for (class_label, combination) in best_combination.into_iter().enumerate() {
+                back_pointers.insert((node.clone(), class_label), combination.unwrap());
+            }
+            log_normalize(best)
+        };
+        for class_label in CLASS_LABELS {
+            table.set_log_pi_value(node, class_label, log_pi[class_label]);
+        }
+        let log_likelihood = self.virtual_likelihood(node)?.map(f64::ln);
+        let children = self.proposition_graph.get_all_forward(node);
+        for (this_index, to_node) in children.iter().enumerate() {
+            let mut log_message = [0f64; 2];
+            for class_label in CLASS_LABELS {
+                let mut log_lambda_part = 0f64;
+                for (other_index, other_child) in children.iter().enumerate() {
+                    if other_index != this_index {
+                        log_lambda_part += table.get_log_lambda_message(other_child, node, class_label).unwrap();
+                    }
+                }
+                log_message[class_label] = log_likelihood[class_label] + log_pi[class_label] + log_lambda_part;
+            }
+            table.send_log_pi_message(node, to_node, log_message, self.config.damping);
+        }
+        Ok(())
+    }
+
+    // As `lambda_visit_node`, with the sum over the other parents and this node replaced by a max.
+    fn max_lambda_visit_node(
+        &self,
+        table: &mut HashMapBeliefTable,
+        node: &PropositionNode,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.intervention_for(node).is_some() {
+            for class_label in CLASS_LABELS {
+                table.set_log_lambda_value(node, class_label, 0f64);
+                for parent in &self.proposition_graph.get_all_backward(node) {
+                    table.set_log_lambda_message(node, parent, class_label, 0f64);
+                }
+            }
+            return Ok(());
+        }
+        let log_lambda = match self.evidence_for(node)? {
+            Some(evidence) => log_pair(evidence),
+            None => {
+                let mut log_values = self.virtual_likelihood(node)?.map(f64::ln);
+                for class_label in CLASS_LABELS {
+                    for child_node in &self.proposition_graph.get_all_forward(node) {
+                        log_values[class_label] += table.get_log_lambda_message(child_node, node, class_label).unwrap();
+                    }
+                }
+                log_normalize(log_values)
+            }
+        };
+        for class_label in CLASS_LABELS {
+            table.set_log_lambda_value(node, class_label, log_lambda[class_label]);
+        }
+        let parent_nodes = self.proposition_graph.get_all_backward(node);
+        let all_combinations = compute_each_combination(&parent_nodes);
+        for (to_index, to_parent) in parent_nodes.iter().enumerate() {
+            let mut log_message = [f64::NEG_INFINITY; 2];
+            for combination in &all_combinations {
+                let mut log_pi_product = 0f64;
+                for (other_index, other_parent) in parent_nodes.iter().enumerate() {
+                    if other_index != to_index {
+                        let outcome = if combination[other_parent] { 1 } else { 0 };
+                        log_pi_product += table.get_log_pi_message(other_parent, node, outcome).unwrap();
+                    }
+                }
+                let true_marginal = self.score_factor_assignment(&parent_nodes, combination, node)?;
+                let log_score = (true_marginal.ln() + log_lambda[1])
+                    .max((1f64 - true_marginal).ln() + log_lambda[0])
+                    + log_pi_product;
+                let parent_outcome = if combination[to_parent] { 1 } else { 0 };
+                log_message[parent_outcome] = log_message[parent_outcome].max(log_score);
+            }
+            table.send_log_lambda_message(node, to_parent, log_message, self.config.damping);
+        }
+        Ok(())
+    }
+
+    // Reads values off the back-pointers, from the leaves up. A node no child has assigned
+    // takes the larger of its max-product beliefs.
+    fn decode_back_pointers(
+        &self,
+        table: &HashMapBeliefTable,
+        back_pointers: &BackPointers,
+    ) -> Result<HashMap<PropositionNode, bool>, Box<dyn Error>> {
+        let mut values: HashMap<PropositionNode, bool> = self
+            .bfs_order
+            .iter()
+            .filter_map(|node| self.intervention_for(node).map(|value| (node.clone(), value)))
+            .collect();
+        for node in self.bfs_order.iter().rev() {
+            let value = match values.get(node) {
+                Some(value) => *value,
+                None => {
+                    let value = match self.evidence_for(node)? {
+                        Some(evidence) => evidence >= 0.5,
+                        None => {
+                            let log_belief = |outcome: usize| {
+                                table.get_log_pi_value(node, outcome).unwrap()
+                                    + table.get_log_lambda_value(node, outcome).unwrap()
+                            };
+                            log_belief(1) > log_belief(0)
+                        }
+                    };
+                    values.insert(node.clone(), value);
+                    value
+                }
+            };
+            if self.evidence_for(node)?.is_some() || self.intervention_for(node).is_some() {
+                continue;
+            }
+            let outcome = if value { 1 } else { 0 };
+            if let Some(parents) = back_pointers.get(&(node.clone(), outcome)) {
+                for (parent, parent_value) in parents {
+                    values.entry(parent.clone()).or_insert(*parent_value);
+                }
+            }
+        }
+        // A group is the AND of its terms, whatever the back-pointers said.
+        for node in &self.bfs_order {
+            if node.is_group() {
+                let parents = self.proposition_graph.get_all_backward(node);
+                let value = parents.iter().all(|parent| values[parent]);
+                values.insert(node.clone(), value);
+            }
+        }
+        Ok(values)
+    }
+
+    fn log_joint_probability(&self, values: &HashMap<PropositionNode, bool>) -> Result<f64, Box<dyn Error>> {
+        let mut result = 0f64;
+        for node in &self.bfs_order {
+            if node.is_group() || self.intervention_for(node).is_some() {
+                continue;
+            }
+            let parent_nodes = self.proposition_graph.get_all_backward(node);
+            let probability = if parent_nodes.is_empty() {
+                self.model.priors.prior_for_proposition(&node.extract_single())?
+            } else {
+                let combination = parent_nodes
+                    .iter()
+                    .map(|parent| (parent.clone(), values[parent]))
+                    .collect();
+                self.score_factor_assignment(&parent_nodes, &combination, node)?
+            };
+            let value = values[node];
+            result += if value { probability } else { 1f64 - probability }.ln();
+            if let Some(evidence) = self.evidence_for(node)? {
+                result += if value { evidence } else { 1f64 - evidence }.ln();
+            } else if let Some(ratio) = self.virtual_evidence_for(node)? {
+                result += likelihood_from_ratio(ratio)[if value { 1 } else { 0 }].ln();
+            }
+        }
+        Ok(result)
+    }
+
+    // The most probable joint assignment of the unobserved propositions given the evidence in
+    // `fact_memory`, by max-product pi and lambda passes. Exact when the graph is a polytree;
+    // on loopy graphs the passes repeat under the same limits as `propagate_until_converged`.
+    // The current chart in `data` is left alone.
+    pub fn most_probable_explanation(&self) -> Result<MostProbableExplanation, Box<dyn Error>> {
+        self.refresh_factor_cache()?;
+        let mut table = HashMapBeliefTable::new(self.bfs_order.clone());
+        for node in &self.bfs_order {
+            for class_label in CLASS_LABELS {
+                table.set_log_lambda_value(node, class_label, 0f64);
+                for parent in &self.proposition_graph.get_all_backward(node) {
+                    table.set_log_lambda_message(node, parent, class_label, 0f64);
+                }
+            }
+        }
+        let mut back_pointers = BackPointers::new();
+        let mut residual = f64::INFINITY;
+        let mut iterations = 0;
+        while iterations < self.config.max_iterations && residual > self.config.tolerance {
+            table.take_largest_change();
+            for node in &self.bfs_order {
+                self.max_pi_visit_node(&mut table, &mut back_pointers, node)?;
+            }
+            for node in self.bfs_order.iter().rev() {
+                self.max_lambda_visit_node(&mut table, node)?;
+            }
+            residual = table.take_largest_change();
+            iterations += 1;
+        }
+        // One more pi pass, so the back-pointers see the final lambda messages.
+        for node in &self.bfs_order {
+            self.max_pi_visit_node(&mut table, &mut back_pointers, node)?;
+        }
+        let values = self.decode_back_pointers(&table, &back_pointers)?;
+        let log_probability = self.log_joint_probability(&values)?;
+        let mut assignment = vec![];
+        for node in &self.bfs_order {
+            if node.is_single() && self.evidence_for(node)?.is_none() && self.intervention_for(node).is_none() {
+                assignment.push((format!("{:?}", node), values[node]));
+            }
+        }
+        Ok(MostProbableExplanation {
+            assignment,
+            log_probability,
+            probability: log_probability.exp(),
+            convergence: ConvergenceReport {
+                iterations,
+                residual,
+                converged: residual <= self.config.tolerance,
+                schedule: MessageSchedule::Sweep,
+                message_updates: iterations * self.messages_per_sweep(),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::{jack, WeightedWorld};
+
+    #[test]
+    fn long_chain_explanations_stay_finite() {
+        // alpha0 -> alpha1 -> ... -> alpha400, with the evidence breaking the last link.
+        let mut world = WeightedWorld::new(&[]);
+        let length = 400;
+        let name = |index: usize| format!("alpha{}", index);
+        for index in 0..length {
+            world.link(&[&name(index)], &name(index + 1), 10f64);
+        }
+        world.observe(&name(0), 1f64);
+        world.observe(&name(length), 0f64);
+        let inferencer = world.inferencer(&[&name(length)]);
+        let explanation = inferencer.most_probable_explanation().unwrap();
+        // The root's prior, one broken link and the rest kept.
+        let link = 1f64 / (1f64 + (-20f64).exp());
+        let expected = 0.5f64.ln() + (1f64 - link).ln() + (length - 1) as f64 * link.ln();
+        assert!((explanation.log_probability - expected).abs() < 1e-6, "{}", explanation.log_probability);
+        let conclusion = inferencer
+            .explain_conclusion(&PropositionNode::from_single(&jack(&name(length))))
+            .unwrap();
+        assert!(conclusion.probability.is_finite());
+    }
+}
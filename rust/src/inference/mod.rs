@@ -1,5 +1,17 @@
 pub mod table;
-pub mod inference;
+pub mod inferencer;
 pub mod graph;
 pub mod pi;
-pub mod lambda;
\ No newline at end of file
+pub mod lambda;
+pub mod convergence;
+pub mod exact;
+pub mod sampling;
+pub mod gibbs;
+pub mod likelihood;
+pub mod max_product;
+pub mod fan_in;
+pub mod intervention;
+pub mod incremental;
+pub mod forward;
+pub mod factor_cache;
+pub mod query;
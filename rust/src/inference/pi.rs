@@ -1,9 +1,9 @@
 use super::{
-    inference::{compute_each_combination, groups_from_backlinks, Inferencer},
-    table::{GenericNodeType, PropositionNode},
+    inferencer::{compute_each_combination, groups_from_backlinks, Inferencer},
+    table::{log_normalize, log_sum_exp, GenericNodeType, PropositionNode},
 };
 use crate::{
-    inference::inference::build_factor_context_for_assignment,
+    inference::inferencer::build_factor_context_for_assignment,
     model::{objects::EXISTENCE_FUNCTION, weights::CLASS_LABELS},
     print_blue, print_green, print_red,
 };
@@ -19,28 +19,31 @@ impl Inferencer {
     }
 
     pub fn pi_visit_node(&mut self, from_node: &PropositionNode) -> Result<(), Box<dyn Error>> {
-        if !self.is_root(from_node) {
-            let is_observed = self.is_observed(from_node)?;
-            if is_observed {
-                self.pi_set_from_evidence(from_node)?;
-            } else {
-                self.pi_compute_value(&from_node)?;
-            }
+        self.pi_update_value(from_node)?;
+        self.pi_send_messages(from_node)?;
+        Ok(())
+    }
+
+    pub fn pi_update_value(&mut self, node: &PropositionNode) -> Result<(), Box<dyn Error>> {
+        if let Some(value) = self.intervention_for(node) {
+            self.pi_set_from_intervention(node, value);
+        } else if self.is_observed(node)? {
+            self.pi_set_from_evidence(node)?;
+        } else if self.is_root(node) {
+            self.pi_compute_root(node)?;
         } else {
-            self.pi_compute_root(from_node)?;
+            self.pi_compute_value(node)?;
         }
-        self.pi_send_messages(from_node)?;
         Ok(())
     }
 
+    // An unobserved root takes its pi from the prior for its predicate.
     fn pi_compute_root(&mut self, node: &PropositionNode) -> Result<(), Box<dyn Error>> {
         let root = node.extract_single();
-        assert_eq!(root.predicate.function, EXISTENCE_FUNCTION.to_string());
-        self.data
-            .set_pi_value(&PropositionNode::from_single(&root), 1, 1.0f64);
-        self.data
-            .set_pi_value(&PropositionNode::from_single(&root), 0, 0.0f64);
-
+        let prior = self.model.priors.prior_for_proposition(&root)?;
+        trace!("pi_compute_root {:?} prior {}", &root, prior);
+        self.data.set_pi_value(node, 1, prior);
+        self.data.set_pi_value(node, 0, 1f64 - prior);
         Ok(())
     }
 
@@ -59,58 +62,93 @@ impl Inferencer {
         let is_observed = self.is_observed(node)?;
         assert!(!is_observed);
         let parent_nodes = self.proposition_graph.get_all_backward(node);
+        if let Some([log_false, log_true]) =
+            self.pi_compute_value_without_enumeration(&parent_nodes, node)?
+        {
+            self.data.set_log_pi_value(node, 1, log_true);
+            self.data.set_log_pi_value(node, 0, log_false);
+            return Ok(());
+        }
         let all_combinations = compute_each_combination(&parent_nodes);
-        let mut sum_true = 0f64;
-        let mut sum_false = 0f64;
+        let mut log_terms_true = vec![];
+        let mut log_terms_false = vec![];
         for combination in &all_combinations {
-            let mut product = 1f64;
+            let mut log_product = 0f64;
             for (index, parent_node) in parent_nodes.iter().enumerate() {
                 let boolean_outcome = combination.get(parent_node).unwrap();
                 let usize_outcome = if *boolean_outcome { 1 } else { 0 };
-                let pi_x_z = self
+                let log_pi_x_z = self
                     .data
-                    .get_pi_message(parent_node, node, usize_outcome)
+                    .get_log_pi_message(parent_node, node, usize_outcome)
                     .unwrap();
                 trace!(
-                    "getting pi message parent_node {:?}, node {:?}, usize_outcome {}, pi_x_z {}",
+                    "getting pi message parent_node {:?}, node {:?}, usize_outcome {}, log_pi_x_z {}",
                     &parent_node,
                     &node,
                     usize_outcome,
-                    pi_x_z,
+                    log_pi_x_z,
                 );
-                product *= pi_x_z;
+                log_product += log_pi_x_z;
             }
             let true_marginal =
                 self.score_factor_assignment(&parent_nodes, combination, node)?;
             let false_marginal = 1f64 - true_marginal;
-            sum_true += true_marginal * product;
-            sum_false += false_marginal * product;
+            log_terms_true.push(true_marginal.ln() + log_product);
+            log_terms_false.push(false_marginal.ln() + log_product);
         }
-        self.data.set_pi_value(node, 1, sum_true);
-        self.data.set_pi_value(node, 0, sum_false);
+        let [log_false, log_true] =
+            log_normalize([log_sum_exp(&log_terms_false), log_sum_exp(&log_terms_true)]);
+        self.data.set_log_pi_value(node, 1, log_true);
+        self.data.set_log_pi_value(node, 0, log_false);
         Ok(())
     }
 
     pub fn pi_send_messages(&mut self, node: &PropositionNode) -> Result<(), Box<dyn Error>> {
         let forward_groups = self.proposition_graph.get_all_forward(node);
+        // Virtual evidence on this node is part of what each child hears from it.
+        let log_ratio = self.virtual_evidence_for(node)?.map_or(0f64, f64::ln);
         for (this_index, to_node) in forward_groups.iter().enumerate() {
+            let mut log_message = [0f64; 2];
             for class_label in &CLASS_LABELS {
-                let mut lambda_part = 1f64;
+                let mut log_lambda_part = 0f64;
                 for (other_index, other_child) in forward_groups.iter().enumerate() {
                     if other_index != this_index {
                         let this_lambda = self
                             .data
-                            .get_lambda_message(&other_child, node, *class_label)
+                            .get_log_lambda_message(other_child, node, *class_label)
                             .unwrap();
-                        lambda_part *= this_lambda;
+                        log_lambda_part += this_lambda;
                     }
                 }
-                let pi_part = self.data.get_pi_value(&node, *class_label).unwrap();
-                let message = pi_part * lambda_part;
-                self.data
-                    .set_pi_message(&node, &to_node, *class_label, message);
+                let log_pi_part = self.data.get_log_pi_value(node, *class_label).unwrap();
+                log_message[*class_label] = log_pi_part + log_lambda_part;
             }
+            log_message[1] += log_ratio;
+            self.data.send_log_pi_message(node, to_node, log_message, self.config.damping);
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::{jack, WeightedWorld};
+
+    #[test]
+    fn virtual_evidence_on_a_parent_reaches_its_child() {
+        let mut world = WeightedWorld::new(&[]);
+        world.link(&["a"], "b", 2f64);
+        world.likelihood_ratio("a", 4f64);
+        let mut inferencer = world.inferencer(&["b"]);
+        inferencer.propagate_until_converged(None, &mut |_inferencer| Ok(())).unwrap();
+        // A ratio of 4 on a prior of 1/2 gives P(a) = 4/5, softer than an observation would.
+        let parent = inferencer.get_marginal(&PropositionNode::from_single(&jack("a"))).unwrap();
+        assert!((parent - 0.8).abs() < 1e-9, "{}", parent);
+        // With the weights of `WeightedWorld::link`, P(b | a) = sigmoid(4) and P(b | not a) = sigmoid(-4).
+        let sigmoid = |x: f64| 1f64 / (1f64 + (-x).exp());
+        let expected = 0.8 * sigmoid(4f64) + 0.2 * sigmoid(-4f64);
+        let child = inferencer.get_marginal(&PropositionNode::from_single(&jack("b"))).unwrap();
+        assert!((child - expected).abs() < 1e-9, "{} {}", child, expected);
+    }
+}
@@ -0,0 +1,190 @@
+use super::{
+    convergence::ConvergenceReport,
+    graph::{GraphBuildOptions, PropositionGraph, TruncationReport},
+    inferencer::Inferencer,
+    table::PropositionNode,
+};
+use crate::{
+    common::{
+        interface::BeliefTable, model::InferenceModel, proposition_db::HashMapBeliefTable,
+        resources::FactoryResources, setup::ConfigurationOptions,
+    },
+    model::objects::Proposition,
+};
+use serde::Serialize;
+use std::{collections::HashMap, error::Error, rc::Rc};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct PropositionMarginal {
+    pub proposition: Proposition,
+    pub probability: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QueryResult {
+    // One entry per target, in the order asked.
+    pub marginals: Vec<PropositionMarginal>,
+    pub convergence: ConvergenceReport,
+    pub truncation: TruncationReport,
+}
+
+// What is known when asking a query; a proposition may appear in at most one of these.
+#[derive(Debug, Clone, Default)]
+pub struct QueryEvidence {
+    // Hard evidence: the probability each observed proposition is true, clamping it.
+    pub observed: HashMap<Proposition, f64>,
+    // Virtual evidence: likelihood ratios P(observation | true) / P(observation | false).
+    pub likelihood_ratios: HashMap<Proposition, f64>,
+    // do(proposition = value).
+    pub interventions: HashMap<Proposition, bool>,
+}
+
+impl QueryEvidence {
+    fn check_disjoint(&self) -> Result<(), Box<dyn Error>> {
+        for proposition in self.likelihood_ratios.keys().chain(self.interventions.keys()) {
+            if self.observed.contains_key(proposition) {
+                return Err(format!("{:?} has hard evidence and another kind too.", proposition).into());
+            }
+        }
+        for proposition in self.interventions.keys() {
+            if self.likelihood_ratios.contains_key(proposition) {
+                return Err(format!("{:?} has virtual evidence and is intervened on.", proposition).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl QueryResult {
+    pub fn get(&self, proposition: &Proposition) -> Option<f64> {
+        self.marginals
+            .iter()
+            .find(|marginal| &marginal.proposition == proposition)
+            .map(|marginal| marginal.probability)
+    }
+}
+
+// Answers P(targets | evidence) against a trained model, for callers that embed bayes-star
+// rather than drive it through the REPL. Graph construction, chart initialization and
+// propagation all happen inside `query`.
+pub struct QueryEngine {
+    config: ConfigurationOptions,
+    model: Rc<InferenceModel>,
+}
+
+impl QueryEngine {
+    pub fn new(config: &ConfigurationOptions, model: Rc<InferenceModel>) -> QueryEngine {
+        QueryEngine {
+            config: config.clone(),
+            model,
+        }
+    }
+
+    pub fn from_resources(resources: &FactoryResources) -> Result<QueryEngine, Box<dyn Error>> {
+        let model = InferenceModel::new_shared(resources)?;
+        Ok(QueryEngine::new(&resources.config, model))
+    }
+
+    // `evidence` maps observed propositions to their probability of being true.
+    pub fn query(
+        &self,
+        targets: &[Proposition],
+        evidence: &HashMap<Proposition, f64>,
+    ) -> Result<QueryResult, Box<dyn Error>> {
+        let evidence = QueryEvidence {
+            observed: evidence.clone(),
+            ..QueryEvidence::default()
+        };
+        self.query_with_evidence(targets, &evidence)
+    }
+
+    // P(targets | evidence, do(interventions)): each intervened proposition is forced to its
+    // value and cut from its premises.
+    pub fn query_with_evidence(
+        &self,
+        targets: &[Proposition],
+        evidence: &QueryEvidence,
+    ) -> Result<QueryResult, Box<dyn Error>> {
+        evidence.check_disjoint()?;
+        let fact_memory = HashMapBeliefTable::new();
+        for (proposition, probability) in &evidence.observed {
+            fact_memory.store_proposition_probability(proposition, *probability)?;
+        }
+        for (proposition, ratio) in &evidence.likelihood_ratios {
+            fact_memory.store_likelihood_ratio(proposition, *ratio)?;
+        }
+        let proposition_graph = PropositionGraph::new_shared_for_targets(
+            self.model.graph.clone(),
+            targets,
+            &GraphBuildOptions::from_config(&self.config),
+        )?;
+        let truncation = proposition_graph.truncation.clone();
+        let mut inferencer = Inferencer::new_mutable(
+            &self.config,
+            self.model.clone(),
+            proposition_graph,
+            fact_memory,
+        )?;
+        for (proposition, value) in &evidence.interventions {
+            inferencer.set_intervention(proposition, *value)?;
+        }
+        inferencer.initialize_chart()?;
+        let convergence = inferencer.propagate_until_converged(None, &mut |_inferencer| Ok(()))?;
+        let mut marginals = vec![];
+        for target in targets {
+            marginals.push(PropositionMarginal {
+                proposition: target.clone(),
+                probability: inferencer.get_marginal(&PropositionNode::from_single(target))?,
+            });
+        }
+        Ok(QueryResult {
+            marginals,
+            convergence,
+            truncation,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::proposition_db::RedisBeliefTable;
+    use crate::common::fixtures::{jack, WeightedWorld};
+
+    #[test]
+    fn evidence_kinds_must_not_overlap() {
+        let evidence = |observed: &[&str], ratios: &[&str], interventions: &[&str]| QueryEvidence {
+            observed: observed.iter().map(|name| (jack(name), 1f64)).collect(),
+            likelihood_ratios: ratios.iter().map(|name| (jack(name), 2f64)).collect(),
+            interventions: interventions.iter().map(|name| (jack(name), true)).collect(),
+        };
+        assert!(evidence(&["a"], &["b"], &["c"]).check_disjoint().is_ok());
+        assert!(evidence(&["a"], &["a"], &[]).check_disjoint().is_err());
+        assert!(evidence(&["a"], &[], &["a"]).check_disjoint().is_err());
+        assert!(evidence(&[], &["a"], &["a"]).check_disjoint().is_err());
+    }
+
+    #[test]
+    fn query_evidence_stays_in_memory() {
+        let mut world = WeightedWorld::new(&[]);
+        world.link(&["a"], "b", 2f64);
+        let engine = QueryEngine::from_resources(&world.resources).unwrap();
+        let result = engine
+            .query(&[jack("b")], &HashMap::from([(jack("a"), 1f64)]))
+            .unwrap();
+        assert!(result.convergence.converged);
+        // With the weights of `WeightedWorld::link`, P(b | a) = sigmoid(4).
+        let expected = 1f64 / (1f64 + (-4f64).exp());
+        assert!((result.get(&jack("b")).unwrap() - expected).abs() < 1e-9);
+        let stored = RedisBeliefTable::new_shared(&world.resources.redis).unwrap();
+        assert_eq!(stored.get_proposition_probability(&jack("a")).unwrap(), None);
+
+        let intervened = QueryEvidence {
+            interventions: HashMap::from([(jack("b"), false)]),
+            ..QueryEvidence::default()
+        };
+        let result = engine.query_with_evidence(&[jack("a"), jack("b")], &intervened).unwrap();
+        assert!(result.get(&jack("b")).unwrap() < 1e-9);
+        assert!((result.get(&jack("a")).unwrap() - 0.5).abs() < 1e-9);
+    }
+}
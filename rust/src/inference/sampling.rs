@@ -0,0 +1,129 @@
+use super::{
+    inferencer::{likelihood_from_ratio, Inferencer},
+    table::PropositionNode,
+};
+use std::{collections::HashMap, error::Error};
+
+// The proposition graph by node index in `bfs_order`, which puts parents before children, with a
+// cache of `FactorModel` scores per parent assignment. Samplers only draw single nodes: a group
+// node is the AND of its parents. An intervened node is always drawn as its intervention, whatever
+// its parents, and its evidence is ignored.
+pub struct SamplingNetwork<'a> {
+    inferencer: &'a Inferencer,
+    pub nodes: Vec<PropositionNode>,
+    pub parents: Vec<Vec<usize>>,
+    pub children: Vec<Vec<usize>>,
+    pub is_group: Vec<bool>,
+    pub priors: Vec<f64>,
+    // The likelihood `[1 - e, e]` of hard or virtual evidence on each node.
+    pub evidence: Vec<Option<f64>>,
+    pub interventions: Vec<Option<bool>>,
+    scores: HashMap<(usize, u64), f64>,
+}
+
+impl<'a> SamplingNetwork<'a> {
+    pub fn new(inferencer: &'a Inferencer) -> Result<SamplingNetwork<'a>, Box<dyn Error>> {
+        inferencer.refresh_factor_cache()?;
+        let nodes = inferencer.bfs_order.clone();
+        let index: HashMap<PropositionNode, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.clone(), i))
+            .collect();
+        let lookup = |linked: Vec<PropositionNode>| -> Vec<usize> {
+            linked.iter().map(|node| index[node]).collect()
+        };
+        let mut parents = vec![];
+        let mut children = vec![];
+        let mut is_group = vec![];
+        let mut priors = vec![];
+        let mut evidence = vec![];
+        let mut interventions = vec![];
+        for node in &nodes {
+            let backward = inferencer.proposition_graph.get_all_backward(node);
+            if backward.len() >= 64 {
+                return Err(format!("Sampling cannot index {} parents of {:?}.", backward.len(), node).into());
+            }
+            parents.push(lookup(backward));
+            children.push(lookup(inferencer.proposition_graph.get_all_forward(node)));
+            is_group.push(node.is_group());
+            interventions.push(inferencer.intervention_for(node));
+            if node.is_single() {
+                let single = node.extract_single();
+                priors.push(inferencer.model.priors.prior_for_proposition(&single)?);
+                let virtual_evidence = inferencer
+                    .virtual_evidence_for(node)?
+                    .map(|ratio| likelihood_from_ratio(ratio)[1]);
+                if inferencer.intervention_for(node).is_some() {
+                    evidence.push(None);
+                } else {
+                    let hard = inferencer.fact_memory.get_proposition_probability(&single)?;
+                    evidence.push(hard.or(virtual_evidence));
+                }
+            } else {
+                priors.push(1f64);
+                evidence.push(None);
+            }
+        }
+        Ok(SamplingNetwork {
+            inferencer,
+            nodes,
+            parents,
+            children,
+            is_group,
+            priors,
+            evidence,
+            interventions,
+            scores: HashMap::new(),
+        })
+    }
+
+    // P(node = true | its parents in `state`).
+    pub fn probability_true(&mut self, node: usize, state: &[bool]) -> Result<f64, Box<dyn Error>> {
+        if let Some(value) = self.interventions[node] {
+            return Ok(if value { 1f64 } else { 0f64 });
+        }
+        if self.parents[node].is_empty() {
+            return Ok(self.priors[node]);
+        }
+        let mut bits = 0u64;
+        for (j, parent) in self.parents[node].iter().enumerate() {
+            if state[*parent] {
+                bits |= 1 << j;
+            }
+        }
+        if let Some(score) = self.scores.get(&(node, bits)) {
+            return Ok(*score);
+        }
+        let parent_nodes: Vec<PropositionNode> = self.parents[node]
+            .iter()
+            .map(|parent| self.nodes[*parent].clone())
+            .collect();
+        let combination: HashMap<PropositionNode, bool> = self.parents[node]
+            .iter()
+            .map(|parent| (self.nodes[*parent].clone(), state[*parent]))
+            .collect();
+        let score = self
+            .inferencer
+            .score_factor_assignment(&parent_nodes, &combination, &self.nodes[node])?;
+        self.scores.insert((node, bits), score);
+        Ok(score)
+    }
+
+    pub fn conditional_weight(&mut self, node: usize, state: &[bool]) -> Result<f64, Box<dyn Error>> {
+        let probability = self.probability_true(node, state)?;
+        Ok(if state[node] { probability } else { 1f64 - probability })
+    }
+
+    pub fn evidence_weight(&self, node: usize, value: bool) -> f64 {
+        match self.evidence[node] {
+            Some(probability) if value => probability,
+            Some(probability) => 1f64 - probability,
+            None => 1f64,
+        }
+    }
+
+    pub fn group_value(&self, group: usize, state: &[bool]) -> bool {
+        self.parents[group].iter().all(|parent| state[*parent])
+    }
+}
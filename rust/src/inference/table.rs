@@ -68,7 +68,7 @@ impl PropositionNode {
             GenericNodeType::Single(proposition) => proposition.debug_string(),
             GenericNodeType::Group(group) => group.debug_string(),
         };
-        format!("{}", string_part)
+        string_part.to_string()
     }
 
     pub fn is_single(&self) -> bool {
@@ -99,14 +99,16 @@ impl fmt::Debug for PropositionNode {
     }
 }
 
+// Every value and message is stored as a natural log, so long chains and wide fan-in do not
+// underflow. The plain getters and setters convert to and from probabilities.
 #[derive(Debug, Clone)]
-
 pub struct HashMapBeliefTable {
     pi_values: HashMap<(PropositionNode, usize), f64>,
     lambda_values: HashMap<(PropositionNode, usize), f64>,
     pi_messages: HashMap<(PropositionNode, PropositionNode, usize), f64>,
     lambda_messages: HashMap<(PropositionNode, PropositionNode, usize), f64>,
     bfs_order: Vec<PropositionNode>,
+    largest_change: f64,
 }
 
 fn print_sorted_map(
@@ -114,11 +116,11 @@ fn print_sorted_map(
     bfs_order: &Vec<PropositionNode>,
 ) {
     for proposition in bfs_order {
-        let key = (proposition.clone(), 1);
-        let prob_true = map.get(&key).unwrap();
-        let prob_false = 1.0 - prob_true;
-        let formatted_prob_true = format!("{:.8}", prob_true);
-        let formatted_prob_false = format!("{:.8}", prob_false);
+        let log_false = *map.get(&(proposition.clone(), 0)).unwrap();
+        let log_true = *map.get(&(proposition.clone(), 1)).unwrap();
+        let [log_false, log_true] = log_normalize([log_false, log_true]);
+        let formatted_prob_true = format!("{:.8}", log_true.exp());
+        let formatted_prob_false = format!("{:.8}", log_false.exp());
         println!(
             "{:<12} {:<12} {}",
             formatted_prob_true.green(),
@@ -134,8 +136,7 @@ fn print_sorted_messages(
 ) {
     for from in bfs_order {
         for to in bfs_order {
-            let key = (from.clone(), to.clone(), 1);
-            if let Some(&prob_true) = map.get(&key) {
+            if let Some(prob_true) = normalized_message(map, from, to) {
                 let prob_false = 1.0 - prob_true;
                 let formatted_prob_true = format!("{:.8}", prob_true);
                 let formatted_prob_false = format!("{:.8}", prob_false);
@@ -152,8 +153,8 @@ fn print_sorted_messages(
 }
 
 impl HashMapBeliefTable {
-    pub fn print_table(&self, table_name: &String) {
-        match table_name.as_str() {
+    pub fn print_table(&self, table_name: &str) {
+        match table_name {
             "pv" => {
                 println!("PI VALUES");
                 print_sorted_map(&self.pi_values, &self.bfs_order);
@@ -175,6 +176,75 @@ impl HashMapBeliefTable {
     }
 }
 
+type MessageMap = HashMap<(PropositionNode, PropositionNode, usize), f64>;
+
+// log(sum(exp(values))), without leaving log space. Empty or all -inf gives -inf.
+pub fn log_sum_exp(values: &[f64]) -> f64 {
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY || max.is_nan() {
+        return max;
+    }
+    max + values.iter().map(|value| (value - max).exp()).sum::<f64>().ln()
+}
+
+// log(exp(a) - exp(b)), floored at -inf when rounding leaves b >= a.
+pub fn log_diff_exp(a: f64, b: f64) -> f64 {
+    if b >= a {
+        return f64::NEG_INFINITY;
+    }
+    a + (-(b - a).exp()).ln_1p()
+}
+
+// Shifts a pair of logs so their probabilities sum to 1. A pair that is all -inf is returned as is.
+pub fn log_normalize(values: [f64; 2]) -> [f64; 2] {
+    let norm = log_sum_exp(&values);
+    if norm == f64::NEG_INFINITY {
+        return values;
+    }
+    [values[0] - norm, values[1] - norm]
+}
+
+fn normalized_message(map: &MessageMap, from: &PropositionNode, to: &PropositionNode) -> Option<f64> {
+    let log0 = map.get(&(from.clone(), to.clone(), 0))?;
+    let log1 = map.get(&(from.clone(), to.clone(), 1))?;
+    Some((log1 - log_sum_exp(&[*log0, *log1])).exp())
+}
+
+// Stores `log_message` from `from` to `to`, normalized and with `damping` of the message it
+// replaces mixed in, and returns how much the message's P(true) changed. A new message
+// changes by 1, and one that is not a number by infinity.
+fn send_message(
+    map: &mut MessageMap,
+    from: &PropositionNode,
+    to: &PropositionNode,
+    log_message: [f64; 2],
+    damping: f64,
+) -> f64 {
+    let key = |outcome: usize| (from.clone(), to.clone(), outcome);
+    let old = match (map.get(&key(0)), map.get(&key(1))) {
+        (Some(log0), Some(log1)) => Some(log_normalize([*log0, *log1])),
+        _ => None,
+    };
+    let mut log_message = log_normalize(log_message);
+    if let (Some(old), true) = (old, damping > 0f64) {
+        for outcome in CLASS_LABELS {
+            log_message[outcome] =
+                log_sum_exp(&[(1f64 - damping).ln() + log_message[outcome], damping.ln() + old[outcome]]);
+        }
+    }
+    map.insert(key(0), log_message[0]);
+    map.insert(key(1), log_message[1]);
+    let change = match old {
+        Some(old) => (log_message[1].exp() - old[1].exp()).abs(),
+        None => 1f64,
+    };
+    if change.is_nan() {
+        f64::INFINITY
+    } else {
+        change
+    }
+}
+
 impl HashMapBeliefTable {
     // Constructor to create a new instance
     pub fn new(bfs_order: Vec<PropositionNode>) -> Self {
@@ -184,35 +254,55 @@ impl HashMapBeliefTable {
             pi_messages: HashMap::new(),
             lambda_messages: HashMap::new(),
             bfs_order,
+            largest_change: 0f64,
         }
     }
 
-    // Getter for pi values
-    pub fn get_pi_value(&self, node: &PropositionNode, outcome: usize) -> Option<f64> {
+    pub fn send_log_pi_message(&mut self, from: &PropositionNode, to: &PropositionNode, log_message: [f64; 2], damping: f64) {
+        let change = send_message(&mut self.pi_messages, from, to, log_message, damping);
+        self.largest_change = self.largest_change.max(change);
+    }
+
+    pub fn send_log_lambda_message(&mut self, from: &PropositionNode, to: &PropositionNode, log_message: [f64; 2], damping: f64) {
+        let change = send_message(&mut self.lambda_messages, from, to, log_message, damping);
+        self.largest_change = self.largest_change.max(change);
+    }
+
+    // The largest change of a message sent since the last call.
+    pub fn take_largest_change(&mut self) -> f64 {
+        std::mem::take(&mut self.largest_change)
+    }
+
+    // P(true) of the message once its two outcomes are normalized.
+    pub fn get_normalized_pi_message(&self, from: &PropositionNode, to: &PropositionNode) -> Option<f64> {
+        normalized_message(&self.pi_messages, from, to)
+    }
+
+    pub fn get_normalized_lambda_message(&self, from: &PropositionNode, to: &PropositionNode) -> Option<f64> {
+        normalized_message(&self.lambda_messages, from, to)
+    }
+
+    pub fn get_log_pi_value(&self, node: &PropositionNode, outcome: usize) -> Option<f64> {
         let key = (node.clone(), outcome);
         self.pi_values.get(&key).cloned()
     }
 
-    // Setter for pi values
-    pub fn set_pi_value(&mut self, node: &PropositionNode, outcome: usize, value: f64) {
+    pub fn set_log_pi_value(&mut self, node: &PropositionNode, outcome: usize, value: f64) {
         let key = (node.clone(), outcome);
         self.pi_values.insert(key, value);
     }
 
-    // Getter for lambda values
-    pub fn get_lambda_value(&self, node: &PropositionNode, outcome: usize) -> Option<f64> {
+    pub fn get_log_lambda_value(&self, node: &PropositionNode, outcome: usize) -> Option<f64> {
         let key = (node.clone(), outcome);
         self.lambda_values.get(&key).cloned()
     }
 
-    // Setter for lambda values
-    pub fn set_lambda_value(&mut self, node: &PropositionNode, outcome: usize, value: f64) {
+    pub fn set_log_lambda_value(&mut self, node: &PropositionNode, outcome: usize, value: f64) {
         let key = (node.clone(), outcome);
         self.lambda_values.insert(key, value);
     }
 
-    // Getter for pi messages
-    pub fn get_pi_message(
+    pub fn get_log_pi_message(
         &self,
         from: &PropositionNode,
         to: &PropositionNode,
@@ -222,8 +312,7 @@ impl HashMapBeliefTable {
         self.pi_messages.get(&key).cloned()
     }
 
-    // Setter for pi messages
-    pub fn set_pi_message(
+    pub fn set_log_pi_message(
         &mut self,
         from: &PropositionNode,
         to: &PropositionNode,
@@ -234,8 +323,7 @@ impl HashMapBeliefTable {
         self.pi_messages.insert(key, value);
     }
 
-    // Getter for lambda messages
-    pub fn get_lambda_message(
+    pub fn get_log_lambda_message(
         &self,
         from: &PropositionNode,
         to: &PropositionNode,
@@ -245,8 +333,7 @@ impl HashMapBeliefTable {
         self.lambda_messages.get(&key).cloned()
     }
 
-    // Setter for lambda messages
-    pub fn set_lambda_message(
+    pub fn set_log_lambda_message(
         &mut self,
         from: &PropositionNode,
         to: &PropositionNode,
@@ -256,4 +343,80 @@ impl HashMapBeliefTable {
         let key = (from.clone(), to.clone(), outcome);
         self.lambda_messages.insert(key, value);
     }
+
+    // Getter for pi values
+    pub fn get_pi_value(&self, node: &PropositionNode, outcome: usize) -> Option<f64> {
+        self.get_log_pi_value(node, outcome).map(f64::exp)
+    }
+
+    // Setter for pi values
+    pub fn set_pi_value(&mut self, node: &PropositionNode, outcome: usize, value: f64) {
+        self.set_log_pi_value(node, outcome, value.ln());
+    }
+
+    // Getter for lambda values
+    pub fn get_lambda_value(&self, node: &PropositionNode, outcome: usize) -> Option<f64> {
+        self.get_log_lambda_value(node, outcome).map(f64::exp)
+    }
+
+    // Setter for lambda values
+    pub fn set_lambda_value(&mut self, node: &PropositionNode, outcome: usize, value: f64) {
+        self.set_log_lambda_value(node, outcome, value.ln());
+    }
+
+    // Getter for pi messages
+    pub fn get_pi_message(
+        &self,
+        from: &PropositionNode,
+        to: &PropositionNode,
+        outcome: usize,
+    ) -> Option<f64> {
+        self.get_log_pi_message(from, to, outcome).map(f64::exp)
+    }
+
+    // Setter for pi messages
+    pub fn set_pi_message(
+        &mut self,
+        from: &PropositionNode,
+        to: &PropositionNode,
+        outcome: usize,
+        value: f64,
+    ) {
+        self.set_log_pi_message(from, to, outcome, value.ln());
+    }
+
+    // Getter for lambda messages
+    pub fn get_lambda_message(
+        &self,
+        from: &PropositionNode,
+        to: &PropositionNode,
+        outcome: usize,
+    ) -> Option<f64> {
+        self.get_log_lambda_message(from, to, outcome).map(f64::exp)
+    }
+
+    // Setter for lambda messages
+    pub fn set_lambda_message(
+        &mut self,
+        from: &PropositionNode,
+        to: &PropositionNode,
+        outcome: usize,
+        value: f64,
+    ) {
+        self.set_log_lambda_message(from, to, outcome, value.ln());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_sum_exp_survives_underflow() {
+        // exp(-1000) is 0 as an f64, but the normalized pair is still [1/3, 2/3].
+        let [low, high] = log_normalize([-1000f64, -1000f64 + 2f64.ln()]);
+        assert!((low.exp() - 1f64 / 3f64).abs() < 1e-12);
+        assert!((high.exp() - 2f64 / 3f64).abs() < 1e-12);
+        assert_eq!(log_sum_exp(&[f64::NEG_INFINITY, f64::NEG_INFINITY]), f64::NEG_INFINITY);
+    }
 }
@@ -72,6 +72,33 @@ pub fn compute_search_predicates(
     Ok(result)
 }
 
+// Grounds the premise of `implication` for the roles of a grounded `conclusion`.
+pub fn ground_premise_for_conclusion(
+    implication: &PredicateFactor,
+    conclusion: &Proposition,
+) -> Result<PropositionGroup, Box<dyn Error>> {
+    let mut terms = Vec::new();
+    for (index, proposition) in implication.premise.terms.iter().enumerate() {
+        trace!("Processing term {}: {:?}", index, proposition);
+        let extracted_mapping =
+            extract_premise_role_map(conclusion, &implication.role_maps.role_maps[index]);
+        trace!(
+            "Extracted mapping for term {}: {:?}",
+            index,
+            &extracted_mapping
+        );
+        let extracted_proposition =
+            convert_to_proposition(proposition, &extracted_mapping)?;
+        trace!(
+            "Converted to proposition for term {}: {:?}",
+            index,
+            extracted_proposition
+        );
+        terms.push(extracted_proposition);
+    }
+    Ok(PropositionGroup { terms })
+}
+
 pub fn extract_backimplications_from_proposition(
     graph: &InferenceGraph,
     conclusion: &Proposition,
@@ -85,30 +112,12 @@ pub fn extract_backimplications_from_proposition(
     let mut backimplications = Vec::new();
     for predicate in &search_keys {
         trace!("Processing search_key {:?}", &predicate.hash_string());
-        let implications = graph.predicate_backward_links(&predicate)?;
+        let implications = graph.predicate_backward_links(predicate)?;
         trace!("Found implications {:?}", &implications);
         for implication in &implications {
-            let mut terms = Vec::new();
-            for (index, proposition) in implication.premise.terms.iter().enumerate() {
-                trace!("Processing term {}: {:?}", index, proposition);
-                let extracted_mapping =
-                    extract_premise_role_map(&conclusion, &implication.role_maps.role_maps[index]);
-                trace!(
-                    "Extracted mapping for term {}: {:?}",
-                    index,
-                    &extracted_mapping
-                );
-                let extracted_proposition =
-                    convert_to_proposition(&proposition, &extracted_mapping)?;
-                trace!(
-                    "Converted to proposition for term {}: {:?}",
-                    index,
-                    extracted_proposition
-                );
-                terms.push(extracted_proposition);
-            }
+            let premise = ground_premise_for_conclusion(implication, conclusion)?;
             backimplications.push(PropositionFactor {
-                premise: PropositionGroup { terms },
+                premise,
                 conclusion: conclusion.clone(),
                 inference: implication.clone(),
             });
@@ -139,6 +148,7 @@ pub fn extract_existence_factor_for_predicate(
         premise: premise_group,
         role_maps: mapping_group,
         conclusion: conclusion.clone(),
+        parameter_tie: None,
     };
     trace!("extracted existence predicate {:?}", &factor);
     Ok(factor)
@@ -162,6 +172,7 @@ pub fn extract_existence_factor_for_proposition(
         premise: premise_group,
         role_maps: mapping_group,
         conclusion,
+        parameter_tie: None,
     };
     trace!("extracted existence predicate {:?}", &factor);
     Ok(factor)
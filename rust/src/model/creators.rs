@@ -19,6 +19,7 @@ pub fn implication(
         premise,
         conclusion,
         role_maps,
+        parameter_tie: None,
     }
 }
 
@@ -1,7 +1,12 @@
 use super::choose::extract_backimplications_from_proposition;
 use super::objects::PredicateFactor;
-use super::weights::{negative_feature, positive_feature, ExponentialWeights};
-use crate::common::interface::{BeliefTable, PredictStatistics, TrainStatistics};
+use super::weights::{
+    bias_feature, interaction_feature, is_interaction_feature, negative_feature, positive_feature,
+    ExponentialWeights,
+};
+use crate::common::interface::{
+    BeliefTable, ExplainStatistics, FeatureContribution, PredictStatistics, TrainStatistics,
+};
 use crate::common::model::InferenceModel;
 use crate::common::model::{FactorContext, FactorModel};
 use crate::common::redis::RedisManager;
@@ -59,13 +64,14 @@ pub fn compute_potential(weights: &HashMap<String, f64>, features: &HashMap<Stri
 
 pub fn features_from_factor(
     factor: &FactorContext,
+    config: &ConfigurationOptions,
 ) -> Result<Vec<HashMap<String, f64>>, Box<dyn Error>> {
     let mut vec_result = vec![];
     for class_label in CLASS_LABELS {
         let mut result = HashMap::new();
         for (i, premise) in factor.factor.iter().enumerate() {
             debug!("Processing backimplication {}", i);
-            let feature = premise.inference.unique_key();
+            let feature = premise.inference.parameter_key();
             debug!("Generated unique key for feature: {}", feature);
             let probability = factor.probabilities[i];
             debug!(
@@ -74,13 +80,33 @@ pub fn features_from_factor(
             );
             let posf = positive_feature(&feature, class_label);
             let negf = negative_feature(&feature, class_label);
-            result.insert(posf.clone(), probability);
-            result.insert(negf.clone(), 1.0 - probability);
+            // Premises with tied parameters share a feature, so their values add up.
+            *result.entry(posf.clone()).or_insert(0f64) += probability;
+            *result.entry(negf.clone()).or_insert(0f64) += 1.0 - probability;
             debug!(
                 "Inserted features for backimplication {}: positive - {}, negative - {}",
                 i, posf, negf
             );
         }
+        if config.bias_features {
+            if let Some(first) = factor.factor.first() {
+                let feature = bias_feature(&first.conclusion.predicate.function, class_label);
+                result.insert(feature, 1f64);
+            }
+        }
+        if config.interaction_features {
+            for i in 0..factor.factor.len() {
+                for j in (i + 1)..factor.factor.len() {
+                    let feature = interaction_feature(
+                        &factor.factor[i].inference.parameter_key(),
+                        &factor.factor[j].inference.parameter_key(),
+                        class_label,
+                    );
+                    let value = factor.probabilities[i] * factor.probabilities[j];
+                    result.insert(feature, value);
+                }
+            }
+        }
         vec_result.push(result);
     }
     trace!("features_from_backimplications completed successfully");
@@ -100,6 +126,23 @@ pub fn compute_expected_features(
 
 const LEARNING_RATE: f64 = 0.05;
 
+impl ExponentialModel {
+    // Interaction weights are created lazily, starting at 0, because which implications share a
+    // conclusion is only known once a proposition is grounded.
+    fn read_feature_weights(
+        &self,
+        features: &HashMap<String, f64>,
+    ) -> Result<HashMap<String, f64>, Box<dyn Error>> {
+        let (interactions, base): (Vec<String>, Vec<String>) = features
+            .keys()
+            .cloned()
+            .partition(|feature| is_interaction_feature(feature));
+        let mut weights = self.weights.read_weights(&base)?;
+        weights.extend(self.weights.read_weights_or_zero(&interactions)?);
+        Ok(weights)
+    }
+}
+
 pub fn do_sgd_update(
     weights: &HashMap<String, f64>,
     gold_features: &HashMap<String, f64>,
@@ -129,6 +172,10 @@ impl FactorModel for ExponentialModel {
         implication: &PredicateFactor,
     ) -> Result<(), Box<dyn Error>> {
         self.weights.initialize_weights(implication)?;
+        if self.config.bias_features {
+            self.weights
+                .initialize_bias_weights(&implication.conclusion.function)?;
+        }
         Ok(())
     }
 
@@ -138,7 +185,7 @@ impl FactorModel for ExponentialModel {
         gold_probability: f64,
     ) -> Result<TrainStatistics, Box<dyn Error>> {
         trace!("train_on_example - Getting features from backimplications");
-        let features = match features_from_factor(factor) {
+        let features = match features_from_factor(factor, &self.config) {
             Ok(f) => f,
             Err(e) => {
                 trace!(
@@ -158,10 +205,7 @@ impl FactorModel for ExponentialModel {
                 "train_on_example - Reading weights for class {}",
                 class_label
             );
-            let weight_vector = match self
-                .weights
-                .read_weights(&features[class_label].keys().cloned().collect::<Vec<_>>())
-            {
+            let weight_vector = match self.read_feature_weights(&features[class_label]) {
                 Ok(w) => w,
                 Err(e) => {
                     trace!("train_on_example - Error in read_weights: {:?}", e);
@@ -199,7 +243,7 @@ impl FactorModel for ExponentialModel {
         Ok(TrainStatistics { loss: 1f64 })
     }
     fn predict(&self, factor: &FactorContext) -> Result<PredictStatistics, Box<dyn Error>> {
-        let features = match features_from_factor(factor) {
+        let features = match features_from_factor(factor, &self.config) {
             Ok(f) => f,
             Err(e) => {
                 trace!(
@@ -216,10 +260,7 @@ impl FactorModel for ExponentialModel {
                 trace!("feature {:?} {}", &feature, weight);
             }
             trace!("inference_probability - Reading weights");
-            let weight_vector = match self
-                .weights
-                .read_weights(&this_features.keys().cloned().collect::<Vec<_>>())
-            {
+            let weight_vector = match self.read_feature_weights(this_features) {
                 Ok(w) => w,
                 Err(e) => {
                     trace!("inference_probability - Error in read_weights: {:?}", e);
@@ -229,7 +270,7 @@ impl FactorModel for ExponentialModel {
             for (feature, weight) in weight_vector.iter() {
                 trace!("weight {:?} {}", &feature, weight);
             }
-            let potential = compute_potential(&weight_vector, &this_features);
+            let potential = compute_potential(&weight_vector, this_features);
             trace!("potential for {} {} {:?}", class_label, potential, &factor);
             potentials.push(potential);
         }
@@ -238,4 +279,150 @@ impl FactorModel for ExponentialModel {
         trace!("dot_product: normalization {}, marginal {}", normalization, probability);
         Ok(PredictStatistics { probability })
     }
+
+    fn explain(&self, factor: &FactorContext) -> Result<ExplainStatistics, Box<dyn Error>> {
+        let features = features_from_factor(factor, &self.config)?;
+        let mut contributions = vec![];
+        let mut potentials = vec![];
+        for class_label in CLASS_LABELS {
+            let weight_vector = self.read_feature_weights(&features[class_label])?;
+            potentials.push(compute_potential(&weight_vector, &features[class_label]));
+            for (feature, &value) in &features[class_label] {
+                let weight = weight_vector.get(feature).copied().unwrap_or(0f64);
+                contributions.push(FeatureContribution {
+                    feature: feature.clone(),
+                    class_label,
+                    weight,
+                    value,
+                    contribution: weight * value,
+                });
+            }
+        }
+        contributions.sort_by(|a, b| b.contribution.abs().total_cmp(&a.contribution.abs()));
+        let probability = potentials[1] / (potentials[0] + potentials[1]);
+        Ok(ExplainStatistics {
+            probability,
+            contributions,
+        })
+    }
+
+    fn version(&self) -> Result<Option<u64>, Box<dyn Error>> {
+        Ok(Some(self.weights.version()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::{in_memory, jack, rule};
+    use crate::inference::graph::PropositionFactor;
+    use crate::model::objects::PropositionGroup;
+    use redis::Commands;
+
+    // The factor for `gamma(jack)` under `links`, each from a single premise true with the
+    // matching probability.
+    fn gamma_context(links: &[PredicateFactor], probabilities: Vec<f64>) -> FactorContext {
+        FactorContext {
+            factor: links
+                .iter()
+                .map(|implication| PropositionFactor {
+                    premise: PropositionGroup::new(vec![jack(&implication.premise.terms[0].function)]),
+                    conclusion: jack(&implication.conclusion.function),
+                    inference: implication.clone(),
+                })
+                .collect(),
+            probabilities,
+        }
+    }
+
+    fn stored_weights(resources: &FactoryResources) -> HashMap<String, f64> {
+        resources.redis.get_connection().unwrap().borrow_mut().hgetall("weights").unwrap()
+    }
+
+    #[test]
+    fn tied_implications_share_one_weight() {
+        let (config, resources) = in_memory(&[]);
+        let links = [
+            rule(&["alpha"], "gamma").with_parameter_tie("to_gamma"),
+            rule(&["beta"], "gamma").with_parameter_tie("to_gamma"),
+        ];
+        let mut model = ExponentialModel::new_mutable(&resources).unwrap();
+        for implication in &links {
+            model.initialize_connection(implication).unwrap();
+        }
+        // A positive and a negative feature for each class label, rather than for each implication too.
+        assert_eq!(stored_weights(&resources).len(), 2 * CLASS_LABELS.len());
+
+        let context = gamma_context(&links, vec![0.25, 0.5]);
+        for (class_label, features) in features_from_factor(&context, &config).unwrap().iter().enumerate() {
+            assert_eq!(features.len(), 2);
+            assert_eq!(features[&positive_feature("tie:to_gamma", class_label)], 0.75);
+            assert_eq!(features[&negative_feature("tie:to_gamma", class_label)], 1.25);
+        }
+    }
+
+    #[test]
+    fn bias_weight_is_shared_by_one_conclusion() {
+        let links = [rule(&["alpha"], "gamma"), rule(&["beta"], "gamma"), rule(&["alpha"], "delta")];
+        let bias_weights = |args: &[&str]| {
+            let (_config, resources) = in_memory(args);
+            let mut model = ExponentialModel::new_mutable(&resources).unwrap();
+            for implication in &links {
+                model.initialize_connection(implication).unwrap();
+            }
+            let mut names: Vec<String> = stored_weights(&resources)
+                .into_keys()
+                .filter(|feature| feature.starts_with("b>"))
+                .collect();
+            names.sort();
+            names
+        };
+        assert!(bias_weights(&[]).is_empty());
+        let mut expected: Vec<String> = ["delta", "gamma"]
+            .iter()
+            .flat_map(|function| CLASS_LABELS.map(|class_label| bias_feature(function, class_label)))
+            .collect();
+        expected.sort();
+        assert_eq!(bias_weights(&["--bias_features"]), expected);
+
+        // Each implication into gamma brings the same bias feature.
+        let (config, _resources) = in_memory(&["--bias_features"]);
+        for link in &links[..2] {
+            let features = features_from_factor(&gamma_context(std::slice::from_ref(link), vec![0.5]), &config).unwrap();
+            for (class_label, features) in features.iter().enumerate() {
+                assert_eq!(features[&bias_feature("gamma", class_label)], 1f64);
+            }
+        }
+    }
+
+    #[test]
+    fn interaction_features_need_their_flag_and_enter_predict() {
+        let links = [rule(&["alpha"], "gamma"), rule(&["beta"], "gamma")];
+        let context = gamma_context(&links, vec![0.25, 0.8]);
+        let interaction = |class_label| interaction_feature(&links[0].parameter_key(), &links[1].parameter_key(), class_label);
+        let predict = |args: &[&str], interaction_weight: Option<f64>| {
+            let mut all_args = vec!["--seed", "3"];
+            all_args.extend_from_slice(args);
+            let (config, resources) = in_memory(&all_args);
+            let mut model = ExponentialModel::new_mutable(&resources).unwrap();
+            for implication in &links {
+                model.initialize_connection(implication).unwrap();
+            }
+            let features = features_from_factor(&context, &config).unwrap();
+            let has_interaction = features.iter().enumerate().all(|(class_label, features)| {
+                features.get(&interaction(class_label)) == Some(&(0.25 * 0.8))
+            });
+            assert_eq!(has_interaction, config.interaction_features);
+            if let Some(weight) = interaction_weight {
+                let mut weights = ExponentialWeights::new(resources.redis.get_connection().unwrap());
+                weights.save_weights(&HashMap::from([(interaction(1), weight)])).unwrap();
+            }
+            model.predict(&context).unwrap().probability
+        };
+        let plain = predict(&[], None);
+        // Interaction weights start at zero, so they only matter once learned.
+        assert!((predict(&["--interaction_features"], None) - plain).abs() < 1e-12);
+        assert!(predict(&["--interaction_features"], Some(5f64)) > plain + 0.1);
+        assert!((predict(&[], Some(5f64)) - plain).abs() < 1e-12);
+    }
 }
@@ -0,0 +1,133 @@
+use super::objects::PredicateFactor;
+use super::weights::{bias_feature, negative_feature, positive_feature, ExponentialWeights, CLASS_LABELS};
+use crate::common::graph::InferenceGraph;
+use crate::common::resources::FactoryResources;
+use serde::Serialize;
+use std::error::Error;
+
+// The learned weights of one implication, indexed by class label, and what they imply on their own.
+#[derive(Serialize, Debug, Clone)]
+pub struct ImplicationSummary {
+    pub implication: String,
+    pub parameter_key: String,
+    pub positive_weights: [f64; 2],
+    pub negative_weights: [f64; 2],
+    pub bias_weights: [f64; 2],
+    // P(conclusion | premise) with this implication as the only backimplication.
+    pub probability_if_premise_true: f64,
+    pub probability_if_premise_false: f64,
+    pub influence: f64,
+}
+
+fn probability_for_premise(
+    positive_weights: &[f64; 2],
+    negative_weights: &[f64; 2],
+    bias_weights: &[f64; 2],
+    premise: f64,
+) -> f64 {
+    let potentials: Vec<f64> = CLASS_LABELS
+        .iter()
+        .map(|&class_label| {
+            (positive_weights[class_label] * premise
+                + negative_weights[class_label] * (1f64 - premise)
+                + bias_weights[class_label])
+                .exp()
+        })
+        .collect();
+    potentials[1] / (potentials[0] + potentials[1])
+}
+
+pub fn summarize_implication(
+    weights: &ExponentialWeights,
+    implication: &PredicateFactor,
+) -> Result<ImplicationSummary, Box<dyn Error>> {
+    let key = implication.parameter_key();
+    let mut positive_weights = [0f64; 2];
+    let mut negative_weights = [0f64; 2];
+    let mut bias_weights = [0f64; 2];
+    for class_label in CLASS_LABELS {
+        let posf = positive_feature(&key, class_label);
+        let negf = negative_feature(&key, class_label);
+        let biasf = bias_feature(&implication.conclusion.function, class_label);
+        let read = weights.read_weights(&[posf.clone(), negf.clone()])?;
+        positive_weights[class_label] = read[&posf];
+        negative_weights[class_label] = read[&negf];
+        bias_weights[class_label] = weights.read_weights_or_zero(std::slice::from_ref(&biasf))?[&biasf];
+    }
+    let probability_if_premise_true =
+        probability_for_premise(&positive_weights, &negative_weights, &bias_weights, 1f64);
+    let probability_if_premise_false =
+        probability_for_premise(&positive_weights, &negative_weights, &bias_weights, 0f64);
+    Ok(ImplicationSummary {
+        implication: implication.unique_key(),
+        parameter_key: key,
+        positive_weights,
+        negative_weights,
+        bias_weights,
+        probability_if_premise_true,
+        probability_if_premise_false,
+        influence: (probability_if_premise_true - probability_if_premise_false).abs(),
+    })
+}
+
+// Summaries of every stored implication, most influential first.
+pub fn summarize_all_implications(
+    resources: &FactoryResources,
+) -> Result<Vec<ImplicationSummary>, Box<dyn Error>> {
+    let graph = InferenceGraph::new_mutable(resources)?;
+    let weights = ExponentialWeights::new(resources.redis.get_connection()?);
+    let mut result = vec![];
+    for implication in graph.get_all_implications()? {
+        result.push(summarize_implication(&weights, &implication)?);
+    }
+    result.sort_by(|a, b| b.influence.total_cmp(&a.influence));
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::{rule, WeightedWorld};
+    use std::collections::{HashMap, HashSet};
+
+    #[test]
+    fn most_influential_first_and_ties_share_a_weight() {
+        let mut world = WeightedWorld::new(&[]);
+        world.link(&["a"], "b", 0.5);
+        world.link(&["c"], "d", 2f64);
+        world.link(&["e"], "f", -1f64);
+        let tied = [
+            rule(&["g"], "h").with_parameter_tie("shared"),
+            rule(&["i"], "h").with_parameter_tie("shared"),
+        ];
+        let mut graph = InferenceGraph::new_mutable(&world.resources).unwrap();
+        for implication in &tied {
+            graph.store_predicate_implication(implication).unwrap();
+        }
+        let key = tied[0].parameter_key();
+        let mut weights = ExponentialWeights::new(world.resources.redis.get_connection().unwrap());
+        weights
+            .save_weights(&HashMap::from([
+                (positive_feature(&key, 1), 1.5),
+                (negative_feature(&key, 1), -1.5),
+                (positive_feature(&key, 0), -1.5),
+                (negative_feature(&key, 0), 1.5),
+            ]))
+            .unwrap();
+
+        let summaries = summarize_all_implications(&world.resources).unwrap();
+        let order: Vec<String> = summaries.iter().map(|summary| summary.implication.clone()).collect();
+        // A link of weight w gives P(conclusion | premise) = sigmoid(+-2w), so influence grows with |w|.
+        assert_eq!(order[0], rule(&["c"], "d").unique_key());
+        assert_eq!(
+            HashSet::from([order[1].clone(), order[2].clone()]),
+            tied.iter().map(|implication| implication.unique_key()).collect()
+        );
+        assert_eq!(order[3..], [rule(&["e"], "f").unique_key(), rule(&["a"], "b").unique_key()]);
+        for summary in &summaries[1..3] {
+            assert_eq!(summary.parameter_key, key);
+            assert_eq!(summary.positive_weights, [-1.5, 1.5]);
+            assert_eq!(summary.influence, summaries[1].influence);
+        }
+    }
+}
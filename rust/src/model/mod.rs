@@ -4,4 +4,6 @@ pub mod choose;
 pub mod ops;
 pub mod weights;
 pub mod exponential;
-pub mod config;
\ No newline at end of file
+pub mod config;
+pub mod priors;
+pub mod inspect;
\ No newline at end of file
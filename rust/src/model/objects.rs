@@ -16,12 +16,14 @@ pub enum Domain {
     Jill,
 }
 
-impl Domain {
-    pub fn from_str(s: &str) -> Option<Domain> {
+impl std::str::FromStr for Domain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Domain, String> {
         match s {
-            "Jack" => Some(Domain::Jack),
-            "Jill" => Some(Domain::Jill),
-            _ => None,
+            "Jack" => Ok(Domain::Jack),
+            "Jill" => Ok(Domain::Jill),
+            _ => Err(format!("Domain not recognized: {}", s)),
         }
     }
 }
@@ -84,7 +86,7 @@ impl Argument {
     pub fn convert_to_quantified(&self) -> Argument {
         match self {
             Argument::Constant(arg) => {
-                Argument::Variable(VariableArgument::new(arg.domain.clone()))
+                Argument::Variable(VariableArgument::new(arg.domain))
             }
             Argument::Variable(arg) => Argument::Variable(arg.clone()),
         }
@@ -305,6 +307,9 @@ pub struct PredicateFactor {
     pub premise: PredicateGroup,
     pub role_maps: GroupRoleMap,
     pub conclusion: Predicate,
+    // Implications with the same tie share one set of weights.
+    #[serde(default)]
+    pub parameter_tie: Option<String>,
 }
 
 impl PredicateFactor {
@@ -318,6 +323,19 @@ impl PredicateFactor {
         )
     }
 
+    // The key that weights are stored under: the tie name if the parameters are shared, else the unique key
+    pub fn parameter_key(&self) -> String {
+        match &self.parameter_tie {
+            Some(tie) => format!("tie:{}", tie),
+            None => self.unique_key(),
+        }
+    }
+
+    pub fn with_parameter_tie(mut self, tie: &str) -> Self {
+        self.parameter_tie = Some(tie.to_string());
+        self
+    }
+
     // Generate a feature string based on the premise and the role map
     pub fn feature_string(&self) -> String {
         format!("{}{}", self.premise.hash_string(), self.mapping_string())
@@ -395,3 +413,27 @@ impl fmt::Display for GroupRoleMap {
         write!(f, "[{}]", role_maps_str)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::fixtures::rule;
+
+    #[test]
+    fn parameter_key_survives_serialization() {
+        let untied = rule(&["alpha0"], "alpha1");
+        let tied = rule(&["alpha1"], "alpha2").with_parameter_tie("alpha_link");
+        assert_eq!(tied.parameter_key(), rule(&["alpha0"], "alpha1").with_parameter_tie("alpha_link").parameter_key());
+        for factor in [&untied, &tied] {
+            let record = serde_json::to_string(factor).unwrap();
+            let restored: PredicateFactor = serde_json::from_str(&record).unwrap();
+            assert_eq!(restored.parameter_key(), factor.parameter_key());
+        }
+        // Records stored before ties existed have no `parameter_tie` field.
+        let mut record = serde_json::to_value(&untied).unwrap();
+        record.as_object_mut().unwrap().remove("parameter_tie");
+        let restored: PredicateFactor = serde_json::from_value(record).unwrap();
+        assert_eq!(restored.parameter_tie, None);
+        assert_eq!(restored.parameter_key(), untied.unique_key());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::common::resources::FactoryResources;
+use crate::model::objects::{Proposition, EXISTENCE_FUNCTION};
+use redis::Commands;
+use crate::common::redis::RedisConnection;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::rc::Rc;
+
+// Used for a root whose predicate has no stored prior.
+pub const DEFAULT_PRIOR: f64 = 0.5;
+
+// Prior probability that a proposition is true, per predicate function. Priors are either set
+// explicitly by a scenario or learned from the base rates of the training propositions.
+pub struct PredicatePriors {
+    connection: RefCell<RedisConnection>,
+}
+
+impl PredicatePriors {
+    pub fn new_mutable(resources: &FactoryResources) -> Result<Box<Self>, Box<dyn Error>> {
+        let connection = resources.redis.get_connection()?;
+        Ok(Box::new(PredicatePriors { connection }))
+    }
+
+    pub fn new_shared(resources: &FactoryResources) -> Result<Rc<Self>, Box<dyn Error>> {
+        let connection = resources.redis.get_connection()?;
+        Ok(Rc::new(PredicatePriors { connection }))
+    }
+
+    pub fn store_prior(&self, function: &str, probability: f64) -> Result<(), Box<dyn Error>> {
+        trace!("store_prior - function: {}, probability: {}", function, probability);
+        self.connection
+            .borrow_mut()
+            .hset::<_, _, _, ()>("priors", function, probability)?;
+        Ok(())
+    }
+
+    pub fn read_prior(&self, function: &str) -> Result<Option<f64>, Box<dyn Error>> {
+        let record: Option<String> = self.connection.borrow_mut().hget("priors", function)?;
+        match record {
+            Some(record) => Ok(Some(record.parse::<f64>()?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read_all_priors(&self) -> Result<HashMap<String, f64>, Box<dyn Error>> {
+        let records: HashMap<String, String> = self.connection.borrow_mut().hgetall("priors")?;
+        let mut result = HashMap::new();
+        for (function, record) in records {
+            result.insert(function, record.parse::<f64>()?);
+        }
+        Ok(result)
+    }
+
+    pub fn prior_for_proposition(&self, proposition: &Proposition) -> Result<f64, Box<dyn Error>> {
+        let function = &proposition.predicate.function;
+        if function == EXISTENCE_FUNCTION {
+            return Ok(1f64);
+        }
+        Ok(self.read_prior(function)?.unwrap_or(DEFAULT_PRIOR))
+    }
+
+    // Stores the Laplace-smoothed base rate of each predicate function, replacing any prior
+    // learned or set before. Roots only use these under `learned_priors`, or when the graph was
+    // truncated; otherwise scenarios link their roots to existence, whose prior is 1.
+    pub fn learn_from_base_rates(
+        &self,
+        observations: &[(Proposition, f64)],
+    ) -> Result<(), Box<dyn Error>> {
+        let mut totals: HashMap<String, (f64, f64)> = HashMap::new();
+        for (proposition, probability) in observations {
+            let entry = totals
+                .entry(proposition.predicate.function.clone())
+                .or_insert((0f64, 0f64));
+            entry.0 += probability;
+            entry.1 += 1f64;
+        }
+        for (function, (sum, count)) in &totals {
+            let prior = (sum + 1f64) / (count + 2f64);
+            trace!("learn_from_base_rates - function: {}, prior: {}", function, prior);
+            self.store_prior(function, prior)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{
+        fixtures::{in_memory, jack},
+        interface::BeliefTable,
+        model::InferenceModel,
+        proposition_db::{EmptyBeliefTable, RedisBeliefTable},
+        train::{setup_and_train, TrainingPlan},
+    };
+    use crate::inference::{graph::PropositionGraph, inferencer::Inferencer, table::PropositionNode};
+    use crate::scenarios::one_var::OneVariable;
+
+    #[test]
+    fn learned_prior_is_the_root_belief() {
+        let (config, resources) = in_memory(&["--entities_per_domain", "40", "--seed", "3", "--learned_priors"]);
+        setup_and_train(&resources, &OneVariable {}).unwrap();
+        let plan = TrainingPlan::new(&resources.redis).unwrap();
+        let facts = RedisBeliefTable::new_mutable(&resources.redis).unwrap();
+        let training = plan.get_training_questions().unwrap();
+        let positives: f64 = training
+            .iter()
+            .map(|proposition| facts.get_proposition_probability(proposition).unwrap().unwrap())
+            .sum();
+        let expected = (positives + 1f64) / (training.len() as f64 + 2f64);
+        let model = InferenceModel::new_shared(&resources).unwrap();
+        assert_eq!(model.priors.read_prior("exciting").unwrap(), Some(expected));
+
+        // With no existence backlink, the test proposition is a root and believes its prior.
+        let target = &plan.get_test_questions().unwrap()[0];
+        let graph = PropositionGraph::new_shared(model.graph.clone(), target).unwrap();
+        assert!(graph.roots.contains(target));
+        let fact_memory = EmptyBeliefTable::new_shared(&resources.redis).unwrap();
+        let mut inferencer = Inferencer::new_mutable(&config, model.clone(), graph, fact_memory).unwrap();
+        inferencer.initialize_chart().unwrap();
+        let marginal = inferencer.get_marginal(&PropositionNode::from_single(target)).unwrap();
+        assert!((marginal - expected).abs() < 1e-9);
+
+        // Relearning replaces the old base rate; unknown predicates fall back to the default.
+        model.priors.learn_from_base_rates(&[(jack("exciting"), 1f64)]).unwrap();
+        assert_eq!(model.priors.read_prior("exciting").unwrap(), Some(2f64 / 3f64));
+        assert_eq!(model.priors.prior_for_proposition(&jack("lonely")).unwrap(), DEFAULT_PRIOR);
+        assert_eq!(model.priors.prior_for_proposition(&jack(EXISTENCE_FUNCTION)).unwrap(), 1f64);
+    }
+}
@@ -1,14 +1,15 @@
-use redis::{Commands, Connection};
+use redis::Commands;
+use crate::common::redis::RedisConnection;
 use std::{error::Error, cell::RefCell};
 use crate::model::objects::PredicateFactor;
+use crate::common::random::with_global_rng;
 use rand::Rng;
 use std::collections::HashMap;
 
 pub const CLASS_LABELS: [usize; 2] = [0, 1];
 
-fn random_weight() -> f64 {
-    let mut rng = rand::thread_rng();
-    (rng.gen::<f64>() - rng.gen::<f64>()) / 5.0
+pub fn random_weight() -> f64 {
+    with_global_rng(|rng| (rng.gen::<f64>() - rng.gen::<f64>()) / 5.0)
 }
 
 fn sign_char(value:usize) -> String {
@@ -27,20 +28,51 @@ pub fn negative_feature(feature: &str, class_label : usize) -> String {
     format!("->{} {}", sign_char(class_label), feature)
 }
 
+pub fn bias_feature(conclusion_function: &str, class_label: usize) -> String {
+    format!("b>{} {}", sign_char(class_label), conclusion_function)
+}
+
+// The pair is sorted so that the name does not depend on the order the backimplications were found in.
+pub fn interaction_feature(feature1: &str, feature2: &str, class_label: usize) -> String {
+    let (first, second) = if feature1 <= feature2 {
+        (feature1, feature2)
+    } else {
+        (feature2, feature1)
+    };
+    format!("*>{} {}*{}", sign_char(class_label), first, second)
+}
+
+pub fn is_interaction_feature(feature: &str) -> bool {
+    feature.starts_with("*>")
+}
+
+// Counts writes to the weights, so cached predictions can tell when they are stale.
+const WEIGHTS_VERSION_KEY: &str = "weights_version";
+
 pub struct ExponentialWeights {
-    connection:RefCell<Connection>,
+    connection:RefCell<RedisConnection>,
 }
 
 impl ExponentialWeights {
-    pub fn new(connection: RefCell<Connection>) -> ExponentialWeights {
+    pub fn new(connection: RefCell<RedisConnection>) -> ExponentialWeights {
         ExponentialWeights { connection }
     }
 }
 
 impl ExponentialWeights {
+    pub fn version(&self) -> Result<u64, Box<dyn Error>> {
+        let version: Option<u64> = self.connection.borrow_mut().get(WEIGHTS_VERSION_KEY)?;
+        Ok(version.unwrap_or(0))
+    }
+
+    fn bump_version(&self) -> Result<(), Box<dyn Error>> {
+        self.connection.borrow_mut().incr::<_, _, ()>(WEIGHTS_VERSION_KEY, 1)?;
+        Ok(())
+    }
+
     pub fn initialize_weights(&mut self, implication: &PredicateFactor) -> Result<(), Box<dyn Error>> {
         trace!("initialize_weights - Start: {:?}", implication);
-        let feature = implication.unique_key();
+        let feature = implication.parameter_key();
         trace!("initialize_weights - Unique key: {}", feature);
         for class_label in CLASS_LABELS {
             let posf = positive_feature(&feature, class_label);
@@ -50,21 +82,32 @@ impl ExponentialWeights {
             let weight2 = random_weight();
             trace!("initialize_weights - Generated weights: {}, {}", weight1, weight2);
             trace!("initialize_weights - Setting positive feature weight");
-            self.connection.borrow_mut().hset("weights", &posf, weight1)
+            self.connection.borrow_mut().hset::<_, _, _, ()>("weights", &posf, weight1)
                 .map_err(|e| {
                     trace!("initialize_weights - Error setting positive feature weight: {:?}", e);
                     Box::new(e) as Box<dyn Error>
                 })?;
             trace!("initialize_weights - Setting negative feature weight");
-            self.connection.borrow_mut().hset("weights", &negf, weight2)
+            self.connection.borrow_mut().hset::<_, _, _, ()>("weights", &negf, weight2)
                 .map_err(|e| {
                     trace!("initialize_weights - Error setting negative feature weight: {:?}", e);
                     Box::new(e) as Box<dyn Error>
                 })?;
         }
+        self.bump_version()?;
         trace!("initialize_weights - End");
         Ok(())
     }
+
+    pub fn initialize_bias_weights(&mut self, conclusion_function: &str) -> Result<(), Box<dyn Error>> {
+        trace!("initialize_bias_weights - Conclusion function: {}", conclusion_function);
+        for class_label in CLASS_LABELS {
+            let feature = bias_feature(conclusion_function, class_label);
+            self.connection.borrow_mut().hset::<_, _, _, ()>("weights", &feature, random_weight())?;
+        }
+        self.bump_version()?;
+        Ok(())
+    }
     
     
     pub fn read_weights(&self, features: &[String]) -> Result<HashMap<String, f64>, Box<dyn Error>> {
@@ -91,17 +134,32 @@ impl ExponentialWeights {
         trace!("read_weights - End");
         Ok(weights)
     }
+
+    // Like `read_weights`, but a feature that has never been saved reads as 0.
+    pub fn read_weights_or_zero(&self, features: &[String]) -> Result<HashMap<String, f64>, Box<dyn Error>> {
+        let mut weights = HashMap::new();
+        for feature in features {
+            let record: Option<String> = self.connection.borrow_mut().hget("weights", feature)?;
+            let weight = match record {
+                Some(record) => record.parse::<f64>()?,
+                None => 0f64,
+            };
+            weights.insert(feature.clone(), weight);
+        }
+        Ok(weights)
+    }
     
     pub fn save_weights(&mut self, weights: &HashMap<String, f64>) -> Result<(), Box<dyn Error>> {
         trace!("save_weights - Start");
         for (feature, &value) in weights {
             trace!("save_weights - Saving weight for feature {}: {}", feature, value);
-            self.connection.borrow_mut().hset("weights", feature, value)
+            self.connection.borrow_mut().hset::<_, _, _, ()>("weights", feature, value)
                 .map_err(|e| {
                     trace!("save_weights - Error saving weight for feature {}: {:?}", feature, e);
                     Box::new(e) as Box<dyn Error>
                 })?;
         }
+        self.bump_version()?;
         trace!("save_weights - End");
         Ok(())
     }
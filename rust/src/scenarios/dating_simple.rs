@@ -13,11 +13,10 @@ use crate::{
         objects::{Domain, Entity, RoleMap},
     },
 };
-use rand::Rng; // Import Rng trait
+use crate::common::random::random_f64;
 use std::{collections::HashMap, error::Error};
 fn cointoss() -> f64 {
-    let mut rng = rand::thread_rng(); // Get a random number generator
-    if rng.gen::<f64>() < 0.5 {
+    if random_f64() < 0.5 {
         1.0
     } else {
         0.0
@@ -25,8 +24,7 @@ fn cointoss() -> f64 {
 }
 
 fn weighted_cointoss(threshold: f64) -> f64 {
-    let mut rng = rand::thread_rng(); // Get a random number generator
-    if rng.gen::<f64>() < threshold {
+    if random_f64() < threshold {
         1.0
     } else {
         0.0
@@ -64,7 +62,7 @@ impl ScenarioMaker for SimpleDating {
                 let prefix = if is_test { "test" } else { "train" };
                 let name = format!("{}_{:?}{}", &prefix, domain, i); // Using Debug formatting for Domain enum
                 let entity = Entity {
-                    domain: domain.clone(),
+                    domain: *domain,
                     name: name.clone(),
                 };
                 graph.store_entity(&entity)?;
@@ -180,7 +178,7 @@ impl ScenarioMaker for SimpleDating {
         let xjack = variable(Domain::Jack);
         let xjill = variable(Domain::Jill);
 
-        let implications = vec![
+        let implications = [
             // if jack is lonely, he will date any jill
             implication(
                 conjunction(vec![predicate("lonely".to_string(), vec![
@@ -12,7 +12,8 @@ impl ScenarioMakerFactory {
             "dating_simple" => Ok(Rc::new(SimpleDating {})),
             "dating_triangle" => Ok(Rc::new(EligibilityTriangle {})),
             "one_var" => Ok(Rc::new(OneVariable {})),
-            "long_chain" => Ok(Rc::new(long_chain::Scenario {})),
+            "long_chain" => Ok(Rc::new(long_chain::Scenario { tied: false })),
+            "tied_chain" => Ok(Rc::new(long_chain::Scenario { tied: true })),
             "mid_chain" => Ok(Rc::new(mid_chain::Scenario {})),
             "long_and" => Ok(Rc::new(long_and::Scenario {})),
             "two_var" => Ok(Rc::new(TwoVariable {})),
@@ -38,7 +38,7 @@ impl ScenarioMaker for Scenario {
             let prefix = if is_test { "test" } else { "train" };
             let name = format!("{}_{:?}{}", &prefix, domain, i);
             let jack_entity = Entity {
-                domain: domain.clone(),
+                domain,
                 name: name.clone(),
             };
             graph.store_entity(&jack_entity)?;
@@ -66,7 +66,7 @@ impl ScenarioMaker for Scenario {
                 plan.maybe_add_to_training(is_training, &jack_beta)?;
             }
             {
-                let function = format!("gamma");
+                let function = "gamma".to_string();
                 let jack_gamma = proposition(function, vec![sub(jack.clone())]);
                 proposition_db.store_proposition_boolean(&jack_gamma, p_jack_gamma)?;
                 plan.maybe_add_to_training(is_training, &jack_gamma)?;
@@ -95,7 +95,7 @@ impl ScenarioMaker for Scenario {
                 predicate(format!("{}{}", "alpha", LINK_HEIGHT - 1), vec![sub(xjack.clone())]),
                 predicate(format!("{}{}", "beta", LINK_HEIGHT - 1), vec![sub(xjack.clone())]),
             ]),
-            predicate(format!("gamma"), vec![sub(xjack.clone())]),
+            predicate("gamma".to_string(), vec![sub(xjack.clone())]),
             vec![
                 RoleMap::new(HashMap::from([(
                 "sub".to_string(),
@@ -20,7 +20,10 @@ use std::{collections::HashMap, error::Error};
 
 use super::helpers::weighted_cointoss;
 
-pub struct Scenario {}
+// With `tied`, every link of the chain shares one set of weights.
+pub struct Scenario {
+    pub tied: bool,
+}
 
 const LINK_HEIGHT: u32 = 11;
 
@@ -38,7 +41,7 @@ impl ScenarioMaker for Scenario {
             let prefix = if is_test { "test" } else { "train" };
             let name = format!("{}_{:?}{}", &prefix, domain, i);
             let jack_entity = Entity {
-                domain: domain.clone(),
+                domain,
                 name: name.clone(),
             };
             graph.store_entity(&jack_entity)?;
@@ -64,7 +67,7 @@ impl ScenarioMaker for Scenario {
         for level in 0..(LINK_HEIGHT-1) {
             let fn1 = format!("alpha{}", level);
             let fn2 = format!("alpha{}", level + 1);
-            implications.push(implication(
+            let link = implication(
                 conjunction(vec![predicate(
                     fn1,
                     vec![sub(xjack.clone())],
@@ -74,7 +77,8 @@ impl ScenarioMaker for Scenario {
                     "sub".to_string(),
                     "sub".to_string(),
                 )]))],
-            ));
+            );
+            implications.push(if self.tied { link.with_parameter_tie("alpha_link") } else { link });
         }
         graph.store_predicate_implications(&implications)?;
         Ok(())
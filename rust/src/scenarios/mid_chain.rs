@@ -38,7 +38,7 @@ impl ScenarioMaker for Scenario {
             let prefix = if is_test { "test" } else { "train" };
             let name = format!("{}_{:?}{}", &prefix, domain, i);
             let jack_entity = Entity {
-                domain: domain.clone(),
+                domain,
                 name: name.clone(),
             };
             graph.store_entity(&jack_entity)?;
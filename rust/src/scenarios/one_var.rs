@@ -15,11 +15,10 @@ use crate::{
         objects::{Domain, Entity, RoleMap},
     },
 };
-use rand::Rng; // Import Rng trait
+use crate::common::random::random_f64;
 use std::{collections::HashMap, error::Error};
 fn cointoss() -> f64 {
-    let mut rng = rand::thread_rng(); // Get a random number generator
-    if rng.gen::<f64>() < 0.5 {
+    if random_f64() < 0.5 {
         1.0
     } else {
         0.0
@@ -27,8 +26,7 @@ fn cointoss() -> f64 {
 }
 
 fn weighted_cointoss(threshold: f64) -> f64 {
-    let mut rng = rand::thread_rng(); // Get a random number generator
-    if rng.gen::<f64>() < threshold {
+    if random_f64() < threshold {
         1.0
     } else {
         0.0
@@ -55,7 +53,7 @@ impl ScenarioMaker for OneVariable {
             let prefix = if is_test { "test" } else { "train" };
             let name = format!("{}_{:?}{}", &prefix, domain, i);
             let jack_entity = Entity {
-                domain: domain.clone(),
+                domain,
                 name: name.clone(),
             };
             graph.store_entity(&jack_entity)?;
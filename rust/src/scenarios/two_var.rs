@@ -39,11 +39,12 @@ impl ScenarioMaker for TwoVariable {
             let is_test = i == 0;
             let is_training = !is_test;
             let mut domain_entity_map: HashMap<String, Entity> = HashMap::new();
-            for domain in [Domain::Jack].iter() {
+            {
+                let domain = &Domain::Jack;
                 let prefix = if is_test { "test" } else { "train" };
                 let name = format!("{}_{:?}{}", &prefix, domain, i);
                 let entity = Entity {
-                    domain: domain.clone(),
+                    domain: *domain,
                     name: name.clone(),
                 };
                 graph.store_entity(&entity)?;
@@ -70,8 +71,7 @@ impl ScenarioMaker for TwoVariable {
             }
         }
         let xjack = variable(Domain::Jack);
-        let implications = vec![
-            implication(
+        let implications = [implication(
                 conjunction(vec![predicate("exciting".to_string(), vec![
                     sub(xjack.clone()),
                 ])]),
@@ -83,8 +83,7 @@ impl ScenarioMaker for TwoVariable {
                     "sub".to_string(),
                     "sub".to_string(),
                 )]))],
-            ),
-        ];
+            )];
         for implication in implications.iter() {
             trace!("Storing implication: {:?}", implication);
             graph.store_predicate_implication(implication)?;
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use bayes_star::common::model::InferenceModel;
+use bayes_star::common::proposition_db::EmptyBeliefTable;
+use bayes_star::common::resources::FactoryResources;
+use bayes_star::common::setup::ConfigurationOptions;
+use bayes_star::common::train::{setup_and_train, TrainingPlan};
+use bayes_star::inference::graph::PropositionGraph;
+use bayes_star::inference::inferencer::Inferencer;
+use bayes_star::scenarios::factory::ScenarioMakerFactory;
+use redis::Commands;
+
+fn seeded_config(seed: u64) -> ConfigurationOptions {
+    ConfigurationOptions {
+        scenario_name: "dating_simple".to_string(),
+        test_scenario: None,
+        entities_per_domain: 32,
+        print_training_loss: false,
+        test_example: Some(0),
+        marginal_output_file: None,
+        seed: Some(seed),
+        bias_features: false,
+        interaction_features: false,
+        em_iterations: 0,
+        store_discovered_rules: None,
+        print_json: false,
+        max_iterations: 50,
+        tolerance: 1e-6,
+        damping: 0.0,
+        gibbs_samples: 1000,
+        burn_in: 100,
+        thinning: 1,
+        chains: 4,
+        importance_samples: 10000,
+        max_exact_fan_in: 12,
+        fan_in_samples: 256,
+        break_cycles: false,
+        max_graph_depth: None,
+        max_graph_nodes: None,
+        graph_time_budget_ms: None,
+        schedule: "sweep".to_string(),
+        store_derived: false,
+        forward_rounds: 50,
+        learned_priors: false,
+    }
+}
+
+type Snapshot = BTreeMap<String, String>;
+
+// Trains from scratch and returns the learned weights and the prior marginals of the first test question.
+fn train_and_infer(config: &ConfigurationOptions) -> Result<(Snapshot, Snapshot), Box<dyn Error>> {
+    let resources = FactoryResources::new_in_memory(config);
+    let scenario_maker = ScenarioMakerFactory::new_shared(&resources)?;
+    setup_and_train(&resources, scenario_maker.as_ref())?;
+    let weights: BTreeMap<String, String> = resources
+        .redis
+        .get_connection()?
+        .borrow_mut()
+        .hgetall("weights")?;
+    let plan = TrainingPlan::new(&resources.redis)?;
+    let model = InferenceModel::new_shared(&resources)?;
+    let target = &plan.get_test_questions()?[0];
+    let fact_memory = EmptyBeliefTable::new_shared(&resources.redis)?;
+    let proposition_graph = PropositionGraph::new_shared(model.graph.clone(), target)?;
+    let mut inferencer = Inferencer::new_mutable(config, model, proposition_graph, fact_memory)?;
+    inferencer.initialize_chart()?;
+    inferencer.do_full_forward_and_backward()?;
+    // Keyed by node, since the order of the table follows graph construction.
+    let table = serde_json::to_value(inferencer.build_marginal_table()?)?;
+    let marginals = table["entries"]
+        .as_array()
+        .ok_or("marginal table has no entries")?
+        .iter()
+        .map(|entry| (entry[0].to_string(), entry[1].to_string()))
+        .collect();
+    Ok((weights, marginals))
+}
+
+#[test]
+fn same_seed_gives_same_weights_and_marginals() {
+    let config = seeded_config(7);
+    let (first_weights, first_marginals) = train_and_infer(&config).unwrap();
+    let (second_weights, second_marginals) = train_and_infer(&config).unwrap();
+    assert!(!first_weights.is_empty());
+    assert_eq!(first_weights, second_weights);
+    assert_eq!(first_marginals, second_marginals);
+}